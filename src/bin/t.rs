@@ -3,7 +3,7 @@ use std::{
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use chrono::DateTime;
 use sysinfo::System;
 
 fn main() {
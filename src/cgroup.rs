@@ -0,0 +1,319 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use nix::sys::signal::Signal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    limits, process,
+};
+
+/// 指定 pm 使用的 cgroup 目录，默认为 cgroup v2 挂载点下的 `pm.slice`
+///
+/// 非 root 用户可以指向 systemd 委派给自己的 cgroup
+pub const CGROUP_ROOT_ENV: &str = "PM_CGROUP_ROOT";
+
+/// cgroup 配置，启用后每次启动都放入 pm 目录下单独的 cgroup
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CgroupConfig {
+    /// 没有配置限制时也放入单独的 cgroup，用于跟踪和结束整个进程树
+    #[serde(default)]
+    pub enabled: bool,
+    /// memory.max，字节数，可以带 K/M/G 后缀，或 `max`
+    #[serde(default)]
+    pub memory_max: String,
+    /// cpu.max，CPU 核数如 `1.5`，或原始格式 `50000 100000`，或 `max`
+    #[serde(default)]
+    pub cpu_max: String,
+    /// pids.max，最大进程（线程）数，或 `max`
+    #[serde(default)]
+    pub pids_max: String,
+}
+
+impl CgroupConfig {
+    /// 配置了限制时自动启用
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+            || !self.memory_max.is_empty()
+            || !self.cpu_max.is_empty()
+            || !self.pids_max.is_empty()
+    }
+
+    /// 要写入的控制器文件和内容
+    fn settings(&self) -> Result<Vec<(&'static str, &'static str, String)>> {
+        let mut settings = vec![];
+        if !self.memory_max.is_empty() {
+            let value = match self.memory_max.trim() {
+                "max" | "unlimited" => "max".to_string(),
+                value => limits::parse_size(value)
+                    .ok_or_else(|| Error::Invalid(format!("无效的 memory_max：{}", value)))?
+                    .to_string(),
+            };
+            settings.push(("memory", "memory.max", value));
+        }
+        if !self.cpu_max.is_empty() {
+            settings.push(("cpu", "cpu.max", parse_cpu_max(&self.cpu_max)?));
+        }
+        if !self.pids_max.is_empty() {
+            let value = match self.pids_max.trim() {
+                "max" | "unlimited" => "max".to_string(),
+                value => value
+                    .parse::<u64>()
+                    .map_err(|_| Error::Invalid(format!("无效的 pids_max：{}", value)))?
+                    .to_string(),
+            };
+            settings.push(("pids", "pids.max", value));
+        }
+        Ok(settings)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.settings().map(|_| ())
+    }
+}
+
+/// cpu.max 的周期（微秒）
+const CPU_PERIOD: u64 = 100_000;
+
+fn parse_cpu_max(value: &str) -> Result<String> {
+    let value = value.trim();
+    let invalid = || Error::Invalid(format!("无效的 cpu_max：{}", value));
+    if value == "max" || value == "unlimited" {
+        return Ok(format!("max {}", CPU_PERIOD));
+    }
+    if let Some((quota, period)) = value.split_once(' ') {
+        quota.parse::<u64>().map_err(|_| invalid())?;
+        period.trim().parse::<u64>().map_err(|_| invalid())?;
+        return Ok(value.to_string());
+    }
+    let cores = value.parse::<f64>().map_err(|_| invalid())?;
+    if !(cores > 0.0 && cores.is_finite()) {
+        return Err(invalid());
+    }
+    // 内核要求配额至少 1ms
+    let quota = ((cores * CPU_PERIOD as f64) as u64).max(1000);
+    Ok(format!("{} {}", quota, CPU_PERIOD))
+}
+
+/// 一次运行所在的 cgroup
+#[derive(Debug, Clone)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+/// 从 cgroup 读取的资源用量
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CgroupStats {
+    pub path: String,
+    /// memory.current（字节），没有启用 memory 控制器时为 None
+    pub memory_current: Option<u64>,
+    /// cpu.stat 中的 usage_usec
+    pub cpu_usage_usec: Option<u64>,
+    /// cgroup 中的进程数
+    pub processes: usize,
+}
+
+impl Cgroup {
+    pub fn open(path: &str) -> Self {
+        Cgroup {
+            path: PathBuf::from(path),
+        }
+    }
+
+    /// 为一次运行创建 cgroup 并写入限制
+    ///
+    /// 无法创建 cgroup 时返回错误；无法启用控制器或写入限制时仍返回 cgroup，附带警告
+    pub fn create(
+        name: &str,
+        run_id: &str,
+        config: &CgroupConfig,
+    ) -> Result<(Cgroup, Vec<String>)> {
+        let settings = config.settings()?;
+        let root = root_path()?;
+        fs::create_dir_all(&root).map_err(|e| Error::io(root.to_string_lossy(), e))?;
+
+        let mut warnings = vec![];
+        let mut unavailable = vec![];
+        for (controller, _, _) in &settings {
+            if let Err(e) = enable_controller(&root, controller) {
+                warnings.push(format!("无法启用 {} 控制器: {}", controller, e));
+                unavailable.push(*controller);
+            }
+        }
+
+        let path = root.join(format!("{}.{}", sanitize(name), run_id));
+        fs::create_dir(&path).map_err(|e| Error::io(path.to_string_lossy(), e))?;
+        let cgroup = Cgroup { path };
+        for (controller, file, value) in &settings {
+            if unavailable.contains(controller) {
+                continue;
+            }
+            if let Err(e) = fs::write(cgroup.path.join(file), value) {
+                warnings.push(format!("{} = {}: {}", file, value, e));
+            }
+        }
+        Ok((cgroup, warnings))
+    }
+
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap_or_default()
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.join("cgroup.procs").exists()
+    }
+
+    /// 子进程加入 cgroup 时写入的文件
+    fn procs_path(&self) -> PathBuf {
+        self.path.join("cgroup.procs")
+    }
+
+    /// cgroup 中的全部进程，包括子孙进程
+    pub fn pids(&self) -> Result<Vec<u32>> {
+        let path = self.procs_path();
+        let data = fs::read_to_string(&path).map_err(|e| Error::io(path.to_string_lossy(), e))?;
+        let mut pids = data
+            .split_whitespace()
+            .filter_map(|it| it.parse().ok())
+            .collect::<Vec<_>>();
+        pids.sort_unstable();
+        Ok(pids)
+    }
+
+    pub fn stats(&self) -> Option<CgroupStats> {
+        let read_u64 = |file: &str| {
+            fs::read_to_string(self.path.join(file))
+                .ok()
+                .and_then(|it| it.trim().parse().ok())
+        };
+        let cpu_usage_usec = fs::read_to_string(self.path.join("cpu.stat"))
+            .ok()
+            .and_then(|it| {
+                it.lines()
+                    .find_map(|line| line.strip_prefix("usage_usec "))
+                    .and_then(|it| it.trim().parse().ok())
+            });
+        Some(CgroupStats {
+            path: self.path().to_string(),
+            memory_current: read_u64("memory.current"),
+            cpu_usage_usec,
+            processes: self.pids().ok()?.len(),
+        })
+    }
+
+    /// 结束 cgroup 中的全部进程
+    ///
+    /// 先发送 `signal`，等待 `grace` 后仍有进程则通过 `cgroup.kill` 强制结束（内核不支持时逐个发送 SIGKILL），
+    /// 全部退出后删除 cgroup。返回是否进行了强制结束
+    pub fn terminate(&self, signal: Signal, grace: Duration) -> Result<bool> {
+        process::signal_all(&self.pids()?, signal)?;
+        if self.wait_empty(grace) {
+            self.remove();
+            return Ok(false);
+        }
+
+        self.kill()?;
+        if self.wait_empty(Duration::from_secs(5)) {
+            self.remove();
+            Ok(true)
+        } else {
+            Err(Error::Os(io::Error::other(
+                "发送 SIGKILL 后 cgroup 中仍有进程",
+            )))
+        }
+    }
+
+    /// 立即结束 cgroup 中的全部进程
+    pub fn kill(&self) -> Result<()> {
+        let kill_path = self.path.join("cgroup.kill");
+        if kill_path.exists() {
+            return fs::write(&kill_path, "1")
+                .map_err(|e| Error::io(kill_path.to_string_lossy(), e));
+        }
+        process::signal_all(&self.pids()?, Signal::SIGKILL)
+    }
+
+    fn wait_empty(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let pids = self.pids().unwrap_or_default();
+            if pids.iter().all(|pid| !process::is_alive(*pid)) {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// 删除空的 cgroup，仍有进程时内核会拒绝
+    pub fn remove(&self) {
+        // 进程退出后短时间内可能仍留在 cgroup.procs 中
+        for _ in 0..10 {
+            match fs::remove_dir(&self.path) {
+                Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {
+                    thread::sleep(Duration::from_millis(50))
+                }
+                _ => return,
+            }
+        }
+    }
+}
+
+/// pm 创建 cgroup 的目录
+fn root_path() -> Result<PathBuf> {
+    if let Ok(root) = std::env::var(CGROUP_ROOT_ENV) {
+        return Ok(PathBuf::from(root));
+    }
+    let mounts = fs::read_to_string("/proc/mounts").map_err(|e| Error::io("/proc/mounts", e))?;
+    mounts
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .find(|fields| fields.get(2) == Some(&"cgroup2"))
+        .and_then(|fields| fields.get(1).map(|it| Path::new(it).join("pm.slice")))
+        .ok_or_else(|| Error::Invalid("未找到 cgroup v2 挂载点".to_string()))
+}
+
+/// 在 `root` 及其父 cgroup 中启用控制器，子 cgroup 才能使用
+fn enable_controller(root: &Path, controller: &str) -> io::Result<()> {
+    let available = |dir: &Path| {
+        fs::read_to_string(dir.join("cgroup.controllers"))
+            .map(|it| it.split_whitespace().any(|it| it == controller))
+    };
+    if !available(root)? {
+        if let Some(parent) = root.parent() {
+            if available(parent)? {
+                fs::write(
+                    parent.join("cgroup.subtree_control"),
+                    format!("+{}", controller),
+                )?;
+            }
+        }
+        if !available(root)? {
+            return Err(io::Error::other("当前 cgroup 层级中不可用"));
+        }
+    }
+    fs::write(
+        root.join("cgroup.subtree_control"),
+        format!("+{}", controller),
+    )
+}
+
+/// cgroup 目录名只保留字母、数字、`-`、`_`
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|it| {
+            if it.is_ascii_alphanumeric() || it == '-' || it == '_' {
+                it
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+
+use crate::{
+    error::{Error, Result},
+    ProcessItem,
+};
+
+/// 标签选择器的前缀，如 `tag:db` 表示依赖带有 db 标签的全部进程
+pub const TAG_PREFIX: &str = "tag:";
+
+/// `ele` 直接依赖的进程，按 depends_on 的顺序，标签选择器不包括自己
+pub fn dependencies<'a>(items: &'a [ProcessItem], ele: &ProcessItem) -> Vec<&'a ProcessItem> {
+    let mut deps: Vec<&ProcessItem> = vec![];
+    for selector in &ele.depends_on {
+        let matched = match selector.strip_prefix(TAG_PREFIX) {
+            Some(tag) => items
+                .iter()
+                .filter(|it| it.name != ele.name && it.tags.iter().any(|t| t == tag))
+                .collect::<Vec<_>>(),
+            None => items.iter().filter(|it| &it.name == selector).collect(),
+        };
+        for it in matched {
+            if !deps.iter().any(|dep| dep.name == it.name) {
+                deps.push(it);
+            }
+        }
+    }
+    deps
+}
+
+/// 检查依赖的进程都存在且没有循环依赖
+pub fn check(items: &[ProcessItem]) -> Result<()> {
+    for ele in items {
+        for selector in &ele.depends_on {
+            if !selector.starts_with(TAG_PREFIX) && !items.iter().any(|it| &it.name == selector) {
+                return Err(Error::Invalid(format!(
+                    "{}: 依赖的进程不存在：{}",
+                    ele.name, selector
+                )));
+            }
+        }
+    }
+
+    let mut done = HashSet::new();
+    for ele in items {
+        let mut path = vec![];
+        find_cycle(items, ele, &mut path, &mut done)?;
+    }
+    Ok(())
+}
+
+/// 深度优先遍历，`path` 为当前路径，`done` 为已确认不在循环中的进程
+fn find_cycle<'a>(
+    items: &'a [ProcessItem],
+    ele: &'a ProcessItem,
+    path: &mut Vec<&'a str>,
+    done: &mut HashSet<&'a str>,
+) -> Result<()> {
+    if done.contains(ele.name.as_str()) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|it| *it == ele.name) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(&ele.name);
+        return Err(Error::Invalid(format!(
+            "依赖存在循环：{}",
+            cycle.join(" -> ")
+        )));
+    }
+    path.push(&ele.name);
+    for dep in dependencies(items, ele) {
+        find_cycle(items, dep, path, done)?;
+    }
+    path.pop();
+    done.insert(&ele.name);
+    Ok(())
+}
+
+/// 启动顺序：`names` 及其全部依赖，依赖在前，其余按配置中的顺序
+pub fn start_order(items: &[ProcessItem], names: &[String]) -> Vec<String> {
+    let mut order = vec![];
+    let mut visited = HashSet::new();
+    for ele in items.iter().filter(|it| names.contains(&it.name)) {
+        visit(items, ele, &mut visited, &mut order);
+    }
+    order
+}
+
+fn visit<'a>(
+    items: &'a [ProcessItem],
+    ele: &'a ProcessItem,
+    visited: &mut HashSet<&'a str>,
+    order: &mut Vec<String>,
+) {
+    // 配置未经检查时遇到循环依赖也不会无限递归
+    if !visited.insert(&ele.name) {
+        return;
+    }
+    for dep in dependencies(items, ele) {
+        visit(items, dep, visited, order);
+    }
+    order.push(ele.name.clone());
+}
+
+/// 停止顺序：只包括 `names`，与启动顺序相反，被依赖的进程在后
+pub fn stop_order(items: &[ProcessItem], names: &[String]) -> Vec<String> {
+    let all = items.iter().map(|it| it.name.clone()).collect::<Vec<_>>();
+    let mut order = start_order(items, &all);
+    order.retain(|it| names.contains(it));
+    order.reverse();
+    order
+}
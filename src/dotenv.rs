@@ -0,0 +1,86 @@
+use std::fs;
+
+use crate::error::{Error, Result};
+
+/// 读取 dotenv 格式的环境变量文件
+///
+/// - 每行一个 `KEY=VALUE`，可以带 `export ` 前缀，空行和 `#` 开头的行被忽略
+/// - 双引号中的值支持 `\n`、`\t`、`\"`、`\\` 转义，单引号中的值原样保留
+/// - 不加引号的值去掉首尾空白，` #` 之后视为注释
+/// - 不展开值中的 `$VAR`
+pub fn read_env_file(path: &str) -> Result<Vec<(String, String)>> {
+    let data = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    parse(&data).map_err(|(line, message)| Error::ConfigParse {
+        path: path.to_string(),
+        line: Some(line),
+        column: None,
+        message,
+    })
+}
+
+/// 解析失败时返回行号和原因
+fn parse(data: &str) -> std::result::Result<Vec<(String, String)>, (usize, String)> {
+    let mut vars = vec![];
+    for (idx, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err((idx + 1, format!("缺少 `=`：{}", line)));
+        };
+        let key = key.trim();
+        let mut chars = key.chars();
+        let valid = chars
+            .next()
+            .is_some_and(|it| it.is_ascii_alphabetic() || it == '_')
+            && chars.all(|it| it.is_ascii_alphanumeric() || it == '_' || it == '.');
+        if !valid {
+            return Err((idx + 1, format!("无效的变量名：{}", key)));
+        }
+
+        let value = parse_value(value.trim()).map_err(|e| (idx + 1, e))?;
+        vars.push((key.to_string(), value));
+    }
+    Ok(vars)
+}
+
+fn parse_value(value: &str) -> std::result::Result<String, String> {
+    let rest;
+    let parsed = if let Some(quoted) = value.strip_prefix('\'') {
+        let end = quoted.find('\'').ok_or("缺少结尾的 '")?;
+        rest = &quoted[end + 1..];
+        quoted[..end].to_string()
+    } else if let Some(quoted) = value.strip_prefix('"') {
+        let mut parsed = String::new();
+        let mut chars = quoted.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((i, '"')) => break i,
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, 'n')) => parsed.push('\n'),
+                    Some((_, 't')) => parsed.push('\t'),
+                    Some((_, 'r')) => parsed.push('\r'),
+                    Some((_, ch)) => parsed.push(ch),
+                    None => return Err("缺少结尾的 \"".to_string()),
+                },
+                Some((_, ch)) => parsed.push(ch),
+                None => return Err("缺少结尾的 \"".to_string()),
+            }
+        };
+        rest = &quoted[end + 1..];
+        parsed
+    } else {
+        let end = value.find(" #").unwrap_or(value.len());
+        return Ok(value[..end].trim_end().to_string());
+    };
+
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(parsed)
+    } else {
+        Err(format!("引号后有多余的内容：{}", rest))
+    }
+}
@@ -0,0 +1,116 @@
+use std::{fmt, io};
+
+/// 库中公开接口返回的错误
+#[derive(Debug)]
+pub enum Error {
+    /// 配置文件（或运行状态文件）格式错误，行列号从 1 开始，无法定位时为 None
+    ConfigParse {
+        path: String,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+    /// 读写文件失败
+    Io { path: String, source: io::Error },
+    /// 启动进程失败
+    Spawn { command: String, source: io::Error },
+    /// 执行检测启动命令或pid查询命令失败
+    Detection { command: String, source: io::Error },
+    /// 未找到进程
+    NotFound(String),
+    /// 唯一程序名重复
+    DuplicateName(String),
+    /// 要修改的进程在读取配置后被其他 pm 修改过
+    Conflict { path: String, name: String },
+    /// 配置项无效，如必填字段为空、未知信号、未知模板变量
+    Invalid(String),
+    /// pm 没有权限执行操作，如切换到其他用户
+    Permission(String),
+    /// 与守护进程通信失败或守护进程返回错误
+    Daemon(String),
+    /// 等待超时或进程退出，附带未就绪的唯一程序名
+    NotReady(Vec<String>),
+    /// 其他系统调用失败，如发送信号
+    Os(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn io(path: impl AsRef<str>, source: io::Error) -> Self {
+        Error::Io {
+            path: path.as_ref().to_string(),
+            source,
+        }
+    }
+
+    pub(crate) fn config_parse(path: impl AsRef<str>, e: json5::Error) -> Self {
+        let json5::Error::Message { msg, location } = e;
+        // 语法错误的消息中带有源码片段，只保留最后的说明
+        let summary = msg
+            .lines()
+            .rev()
+            .find_map(|it| it.trim().strip_prefix("= "))
+            .map(|it| it.to_string());
+        let message = summary.unwrap_or(msg);
+        Error::ConfigParse {
+            path: path.as_ref().to_string(),
+            line: location.as_ref().map(|it| it.line),
+            column: location.as_ref().map(|it| it.column),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ConfigParse {
+                path,
+                line: Some(line),
+                column: Some(column),
+                message,
+            } => write!(
+                f,
+                "{} 第 {} 行第 {} 列格式错误: {}",
+                path, line, column, message
+            ),
+            Error::ConfigParse { path, message, .. } => {
+                write!(f, "{} 格式错误: {}", path, message)
+            }
+            Error::Io { path, source } => write!(f, "{}: {}", path, source),
+            Error::Spawn { command, source } => write!(f, "启动失败 `{}`: {}", command, source),
+            Error::Detection { command, source } => {
+                write!(f, "检测命令执行失败 `{}`: {}", command, source)
+            }
+            Error::NotFound(name) => write!(f, "未找到进程：{}", name),
+            Error::DuplicateName(name) => write!(f, "唯一程序名已存在：{}", name),
+            Error::Conflict { path, name } => {
+                write!(f, "{} 的配置在读取后被修改过，请重新修改: {}", name, path)
+            }
+            Error::Invalid(msg) => write!(f, "{}", msg),
+            Error::Permission(msg) => write!(f, "权限不足: {}", msg),
+            Error::Daemon(msg) => write!(f, "守护进程: {}", msg),
+            Error::NotReady(names) => write!(f, "进程未就绪：{}", names.join(", ")),
+            Error::Os(source) => write!(f, "{}", source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. }
+            | Error::Spawn { source, .. }
+            | Error::Detection { source, .. }
+            | Error::Os(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Os(e)
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    template::TemplateContext,
+    ProcessItem, RestartPolicy,
+};
+
+/// 进程分组，成员继承分组的默认配置
+///
+/// 一个进程可以属于多个分组，按分组在配置中的顺序继承，先出现的分组优先
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProcessGroup {
+    pub name: String,
+    /// 成员的唯一程序名
+    #[serde(default)]
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub comment: String,
+    /// 成员没有配置工作目录时使用
+    #[serde(default)]
+    pub cwd: String,
+    /// 成员的环境变量，成员 env 中的同名变量优先
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// 成员没有配置日志路径时写入 `{log_dir}/{name}.log`
+    #[serde(default)]
+    pub log_dir: String,
+    /// 成员没有配置重启策略时使用
+    #[serde(default)]
+    pub restart_policy: Option<RestartPolicy>,
+}
+
+impl ProcessGroup {
+    /// 检查分组名和默认配置中的模板变量，成员是否存在在保存配置时检查
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(Error::Invalid("分组名不能为空".to_string()));
+        }
+        let ctx = TemplateContext::new(&self.name, "", "", "", None);
+        let fields = [("cwd", &self.cwd), ("log_dir", &self.log_dir)]
+            .into_iter()
+            .chain(self.env.values().map(|it| ("env", it)));
+        for (field, value) in fields {
+            ctx.expand(value)
+                .map_err(|e| Error::Invalid(format!("分组 {}: {}: {}", self.name, field, e)))?;
+        }
+        Ok(())
+    }
+
+    /// 只填充成员没有配置的字段，成员配置了默认值（如 never）时也不会被覆盖
+    fn apply(&self, ele: &mut ProcessItem) {
+        if ele.cwd.is_none() && !self.cwd.is_empty() {
+            ele.cwd = Some(self.cwd.clone());
+        }
+        for (key, value) in &self.env {
+            ele.env.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        if ele.log_path.is_none() && !self.log_dir.is_empty() {
+            ele.log_path = Some(format!(
+                "{}/{}.log",
+                self.log_dir.trim_end_matches('/'),
+                ele.name
+            ));
+        }
+        if ele.restart_policy.is_none() {
+            ele.restart_policy = self.restart_policy;
+        }
+    }
+}
+
+/// 应用 `ele` 所在分组的默认配置后的配置，启动和检查进程时使用
+pub fn effective(groups: &[ProcessGroup], ele: &ProcessItem) -> ProcessItem {
+    let mut effective = ele.clone();
+    for group in groups.iter().filter(|it| it.members.contains(&ele.name)) {
+        group.apply(&mut effective);
+    }
+    effective
+}
+
+/// 检查分组名不为空、不重复，成员都存在
+pub fn check(groups: &[ProcessGroup], items: &[ProcessItem]) -> Result<()> {
+    let mut names = HashSet::new();
+    for group in groups {
+        if group.name.trim().is_empty() {
+            return Err(Error::Invalid("分组名不能为空".to_string()));
+        }
+        if !names.insert(group.name.as_str()) {
+            return Err(Error::Invalid(format!("分组已存在：{}", group.name)));
+        }
+        if let Some(member) = group
+            .members
+            .iter()
+            .find(|member| !items.iter().any(|it| &it.name == *member))
+        {
+            return Err(Error::Invalid(format!(
+                "分组 {}: 成员不存在：{}",
+                group.name, member
+            )));
+        }
+    }
+    Ok(())
+}
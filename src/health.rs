@@ -0,0 +1,412 @@
+use std::{
+    fmt,
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use nix::{
+    sys::signal::{killpg, Signal},
+    unistd::Pid,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    logs, process,
+    template::TemplateContext,
+};
+
+/// 健康检查，由 `pm status` 和守护进程执行
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HealthCheck {
+    #[serde(flatten)]
+    pub probe: Probe,
+    /// 守护进程的检查间隔（秒）
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// 单次检查的超时（秒）
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// 连续失败多少次视为不健康
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+fn default_interval() -> u64 {
+    10
+}
+
+fn default_timeout() -> u64 {
+    5
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+/// 检查方式
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Probe {
+    /// 执行 `bash -c command`，退出码为 0 视为健康
+    Exec { command: String },
+    /// 能建立 TCP 连接视为健康
+    Tcp {
+        #[serde(default = "default_host")]
+        host: String,
+        port: u16,
+    },
+    /// HTTP GET 返回预期的状态码视为健康，未指定时接受 2xx，只支持 http
+    Http {
+        url: String,
+        #[serde(default)]
+        status: Option<u16>,
+    },
+    /// pid 文件中的进程在运行视为健康
+    PidFile { path: String },
+    /// 日志最后 `lines` 行中有匹配的行视为健康，`path` 为空时使用进程的日志路径
+    LogRegex {
+        pattern: String,
+        #[serde(default)]
+        path: String,
+        #[serde(default = "default_lines")]
+        lines: usize,
+    },
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_lines() -> usize {
+    100
+}
+
+/// 检查结果
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum HealthStatus {
+    Healthy,
+    /// 不健康，附带最近一次失败的原因
+    Unhealthy(String),
+}
+
+impl fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthStatus::Healthy => write!(f, "健康"),
+            HealthStatus::Unhealthy(reason) => write!(f, "不健康: {}", reason),
+        }
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Probe::Exec { command } => write!(f, "exec:{}", command),
+            Probe::Tcp { host, port } if host.contains(':') => {
+                write!(f, "tcp:[{}]:{}", host, port)
+            }
+            Probe::Tcp { host, port } => write!(f, "tcp:{}:{}", host, port),
+            Probe::Http { url, .. } => write!(f, "http:{}", url),
+            Probe::PidFile { path } => write!(f, "pid-file:{}", path),
+            Probe::LogRegex { pattern, .. } => write!(f, "log-regex:{}", pattern),
+        }
+    }
+}
+
+impl std::str::FromStr for Probe {
+    type Err = String;
+
+    /// 解析命令行中的 `类型:参数`，如 `tcp:8080`、`http:http://127.0.0.1:8080/health`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (kind, arg) = s
+            .split_once(':')
+            .ok_or_else(|| format!("格式应为 类型:参数：{}", s))?;
+        let probe = match kind {
+            "exec" => Probe::Exec {
+                command: arg.to_string(),
+            },
+            "tcp" => {
+                let (host, port) = parse_tcp_addr(arg)?;
+                Probe::Tcp { host, port }
+            }
+            "http" => Probe::Http {
+                url: arg.to_string(),
+                status: None,
+            },
+            "pid-file" => Probe::PidFile {
+                path: arg.to_string(),
+            },
+            "log-regex" => Probe::LogRegex {
+                pattern: arg.to_string(),
+                path: String::new(),
+                lines: default_lines(),
+            },
+            _ => {
+                return Err(format!(
+                    "未知的检查类型 {}，可选 exec、tcp、http、pid-file、log-regex",
+                    kind
+                ))
+            }
+        };
+        Ok(probe)
+    }
+}
+
+/// 拆分 `端口`、`主机:端口` 或 `[IPv6 地址]:端口`，返回的主机不带方括号
+fn parse_tcp_addr(arg: &str) -> std::result::Result<(String, u16), String> {
+    let (host, port) = if let Some(rest) = arg.strip_prefix('[') {
+        let (host, port) = rest
+            .split_once("]:")
+            .ok_or_else(|| format!("格式应为 [IPv6 地址]:端口：{}", arg))?;
+        (host.to_string(), port)
+    } else {
+        match arg.split_once(':') {
+            Some((_, port)) if port.contains(':') => {
+                return Err(format!(
+                    "IPv6 地址需要用方括号括起来，如 [::1]:8080：{}",
+                    arg
+                ))
+            }
+            Some((host, port)) => (host.to_string(), port),
+            None => (default_host(), arg),
+        }
+    };
+    let port = port.parse().map_err(|_| format!("无效的端口：{}", port))?;
+    Ok((host, port))
+}
+
+impl HealthCheck {
+    pub fn new(probe: Probe) -> Self {
+        HealthCheck {
+            probe,
+            interval: default_interval(),
+            timeout: default_timeout(),
+            retries: default_retries(),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval.max(1))
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.max(1))
+    }
+
+    /// 连续失败达到该次数视为不健康，至少为 1
+    pub fn retries(&self) -> u32 {
+        self.retries.max(1)
+    }
+
+    /// 展开检查命令、地址和路径中的模板变量，正则表达式不展开
+    pub fn expand(&self, ctx: &TemplateContext) -> Result<HealthCheck> {
+        let probe = match &self.probe {
+            Probe::Exec { command } => Probe::Exec {
+                command: ctx.expand(command)?,
+            },
+            Probe::Tcp { host, port } => Probe::Tcp {
+                host: ctx.expand(host)?,
+                port: *port,
+            },
+            Probe::Http { url, status } => Probe::Http {
+                url: ctx.expand(url)?,
+                status: *status,
+            },
+            Probe::PidFile { path } => Probe::PidFile {
+                path: ctx.expand(path)?,
+            },
+            Probe::LogRegex {
+                pattern,
+                path,
+                lines,
+            } => Probe::LogRegex {
+                pattern: pattern.clone(),
+                path: ctx.expand(path)?,
+                lines: *lines,
+            },
+        };
+        Ok(HealthCheck {
+            probe,
+            ..self.clone()
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |msg: String| Err(Error::Invalid(format!("health: {}", msg)));
+        match &self.probe {
+            Probe::Exec { command } if command.trim().is_empty() => {
+                invalid("检查命令不能为空".to_string())
+            }
+            Probe::Tcp { port: 0, .. } => invalid("端口不能为 0".to_string()),
+            Probe::Http { url, .. } => parse_http_url(url).map(|_| ()).or_else(invalid),
+            Probe::PidFile { path } if path.is_empty() => {
+                invalid("pid 文件路径不能为空".to_string())
+            }
+            Probe::LogRegex { pattern, .. } => match Regex::new(pattern) {
+                Err(e) => invalid(format!("无效的正则表达式：{}", e)),
+                Ok(_) => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// 执行一次检查，`log_path` 为进程的日志路径
+    pub fn probe(&self, log_path: &str) -> std::result::Result<(), String> {
+        let timeout = self.timeout();
+        match &self.probe {
+            Probe::Exec { command } => probe_exec(command, timeout),
+            Probe::Tcp { host, port } => connect(host, *port, timeout).map(|_| ()),
+            Probe::Http { url, status } => probe_http(url, *status, timeout),
+            Probe::PidFile { path } => {
+                let data = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+                let pid = data
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| format!("{} 中不是有效的pid", path))?;
+                if process::is_alive(pid) {
+                    Ok(())
+                } else {
+                    Err(format!("pid {} 未运行", pid))
+                }
+            }
+            Probe::LogRegex {
+                pattern,
+                path,
+                lines,
+            } => {
+                let path = if path.is_empty() { log_path } else { path };
+                if path.is_empty() {
+                    return Err("没有配置日志路径".to_string());
+                }
+                let tail = logs::tail_lines(path, *lines).map_err(|e| e.to_string())?;
+                if grep(pattern, &tail)? {
+                    Ok(())
+                } else {
+                    Err(format!("最后 {} 行日志中没有匹配 {}", lines, pattern))
+                }
+            }
+        }
+    }
+}
+
+/// 检查命令在单独的进程组中执行，超时后结束整个进程组，不会留下它启动的子进程
+fn probe_exec(command: &str, timeout: Duration) -> std::result::Result<(), String> {
+    let mut child = Command::new("bash")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .map_err(|e| format!("执行失败: {}", e))?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("命令退出码 {}", status.code().unwrap_or(-1))),
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL);
+                let _ = child.wait();
+                return Err(format!("命令超过 {}s 未结束", timeout.as_secs()));
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+fn connect(host: &str, port: u16, timeout: Duration) -> std::result::Result<TcpStream, String> {
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("解析 {} 失败: {}", host, e))?;
+    let mut last_error = format!("{} 没有可用的地址", host);
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = format!("连接 {} 失败: {}", addr, e),
+        }
+    }
+    Err(last_error)
+}
+
+/// 拆分 `http://host[:port]/path`
+fn parse_http_url(url: &str) -> std::result::Result<(String, u16, String), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("只支持 http:// 地址：{}", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse().map_err(|_| format!("无效的端口：{}", url))?,
+        ),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(format!("缺少主机名：{}", url));
+    }
+    Ok((host.to_string(), port, path.to_string()))
+}
+
+fn probe_http(
+    url: &str,
+    expected: Option<u16>,
+    timeout: Duration,
+) -> std::result::Result<(), String> {
+    let (host, port, path) = parse_http_url(url)?;
+    let mut stream = connect(&host, port, timeout)?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .map_err(|e| e.to_string())?;
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: pm\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| format!("发送请求失败: {}", e))?;
+
+    // 只需要状态行
+    let mut head = Vec::new();
+    let mut buf = [0u8; 256];
+    while !head.contains(&b'\n') && head.len() < 4096 {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => head.extend_from_slice(&buf[..n]),
+            Err(e) => return Err(format!("读取响应失败: {}", e)),
+        }
+    }
+    let head = String::from_utf8_lossy(&head);
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|it| it.parse::<u16>().ok())
+        .ok_or_else(|| "无效的 HTTP 响应".to_string())?;
+    let ok = match expected {
+        Some(expected) => status == expected,
+        None => (200..300).contains(&status),
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("HTTP 状态码 {}", status))
+    }
+}
+
+/// `lines` 中是否有匹配 `pattern` 的行
+fn grep(pattern: &str, lines: &[String]) -> std::result::Result<bool, String> {
+    let regex = Regex::new(pattern).map_err(|e| format!("无效的正则表达式：{}", e))?;
+    Ok(lines.iter().any(|it| regex.is_match(it)))
+}
@@ -0,0 +1,303 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    parallel, ProcessItem, ProcessStatus, ReloadStatus, StartStatus, StopStatus,
+};
+
+/// 协议版本，请求和响应的版本不一致时守护进程拒绝处理
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// 守护进程监听的 Unix 套接字路径
+pub fn socket_path(profile_path: &str) -> String {
+    format!("{}/.pm.sock", profile_path)
+}
+
+/// 请求，每个请求占一行 JSON
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Request {
+    pub version: u32,
+    pub action: Action,
+    /// 要操作的唯一程序名
+    pub names: Vec<String>,
+    /// 同时操作的进程数，旧版本的客户端没有该字段
+    #[serde(default = "default_parallel")]
+    pub parallel: usize,
+}
+
+fn default_parallel() -> usize {
+    parallel::DEFAULT_PARALLEL
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    List,
+    Start,
+    Stop,
+    Restart,
+    Reload,
+    Status,
+    /// 查询日志路径
+    Logs,
+}
+
+/// 响应，每个响应占一行 JSON
+#[derive(Serialize, Deserialize)]
+pub struct Response {
+    pub version: u32,
+    pub body: ResponseBody,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResponseBody {
+    List(Vec<ProcessItem>),
+    Start(Vec<(String, StartStatus)>),
+    Stop(Vec<(String, StopStatus)>),
+    Restart(Vec<(String, StopStatus, StartStatus)>),
+    Reload(Vec<(String, ReloadStatus)>),
+    Status(Vec<ProcessStatus>),
+    /// 唯一程序名 -> 日志路径
+    Logs(Vec<(String, String)>),
+    Error(String),
+}
+
+impl Response {
+    pub fn new(body: ResponseBody) -> Self {
+        Response {
+            version: PROTOCOL_VERSION,
+            body,
+        }
+    }
+}
+
+fn write_line<T: Serialize>(stream: &mut UnixStream, value: &T) -> io::Result<()> {
+    let mut line = json5::to_string(value).map_err(io::Error::other)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    stream.flush()
+}
+
+fn read_line<T: for<'de> Deserialize<'de>>(stream: &UnixStream) -> io::Result<T> {
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    if line.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "连接已关闭"));
+    }
+    json5::from_str(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// 守护进程一端
+pub struct Server {
+    socket_path: String,
+    listener: UnixListener,
+    /// 读取线程读到的请求
+    received_tx: Sender<(Request, Responder)>,
+    received_rx: Receiver<(Request, Responder)>,
+}
+
+impl Server {
+    /// 监听套接字，已有守护进程在运行时返回错误，残留的套接字文件会被清理
+    pub fn bind(profile_path: &str) -> Result<Self> {
+        let socket_path = socket_path(profile_path);
+        if Path::new(&socket_path).exists() {
+            if UnixStream::connect(&socket_path).is_ok() {
+                return Err(Error::Daemon(format!("已在运行：{}", socket_path)));
+            }
+            fs::remove_file(&socket_path).map_err(|e| Error::io(&socket_path, e))?;
+        }
+
+        let listener = UnixListener::bind(&socket_path)
+            .and_then(|it| it.set_nonblocking(true).map(|_| it))
+            .map_err(|e| Error::io(&socket_path, e))?;
+        let (received_tx, received_rx) = mpsc::channel();
+        Ok(Server {
+            socket_path,
+            listener,
+            received_tx,
+            received_rx,
+        })
+    }
+
+    /// 处理所有已读取到的请求，没有请求时立即返回
+    ///
+    /// 每个连接在单独的线程中读取请求，发送请求很慢或不发送的客户端不会阻塞调用方。
+    /// `handler` 可以把 [`Responder`] 移到其他线程中，处理完成后再回复
+    pub fn handle_pending<F>(&self, mut handler: F) -> Result<()>
+    where
+        F: FnMut(Request, Responder),
+    {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(Error::io(&self.socket_path, e)),
+            };
+            let received_tx = self.received_tx.clone();
+            thread::spawn(move || {
+                if let Some(received) = Server::receive(stream) {
+                    let _ = received_tx.send(received);
+                }
+            });
+        }
+
+        while let Ok((request, responder)) = self.received_rx.try_recv() {
+            handler(request, responder);
+        }
+        Ok(())
+    }
+
+    /// 读取一个连接的请求，无效的请求直接回复错误
+    fn receive(stream: UnixStream) -> Option<(Request, Responder)> {
+        // 单个连接出错不影响守护进程
+        stream.set_nonblocking(false).ok()?;
+        stream.set_read_timeout(Some(Duration::from_secs(5))).ok()?;
+
+        let request = read_line::<Request>(&stream);
+        let responder = Responder { stream };
+        match request {
+            Ok(request) if request.version != PROTOCOL_VERSION => {
+                responder.send(Response::new(ResponseBody::Error(format!(
+                    "协议版本不一致：守护进程 {}，客户端 {}",
+                    PROTOCOL_VERSION, request.version
+                ))));
+                None
+            }
+            Ok(request) => Some((request, responder)),
+            Err(e) => {
+                responder.send(Response::new(ResponseBody::Error(format!(
+                    "无效的请求：{}",
+                    e
+                ))));
+                None
+            }
+        }
+    }
+}
+
+/// 回复一个请求的连接
+pub struct Responder {
+    stream: UnixStream,
+}
+
+impl Responder {
+    pub fn send(mut self, response: Response) {
+        // 客户端提前断开不影响守护进程
+        let _ = write_line(&mut self.stream, &response);
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket_path);
+    }
+}
+
+/// CLI 一端
+pub struct Client {
+    socket_path: String,
+}
+
+impl Client {
+    /// 守护进程没有运行时返回 None，此时 CLI 直接操作进程
+    pub fn connect(profile_path: &str) -> Option<Self> {
+        let socket_path = socket_path(profile_path);
+        UnixStream::connect(&socket_path).ok()?;
+        Some(Client { socket_path })
+    }
+
+    pub fn call(&self, action: Action, names: Vec<String>) -> Result<ResponseBody> {
+        self.call_parallel(action, names, parallel::DEFAULT_PARALLEL)
+    }
+
+    /// 同时最多操作 `parallel` 个进程，只对启动、停止、查询状态有效
+    pub fn call_parallel(
+        &self,
+        action: Action,
+        names: Vec<String>,
+        parallel: usize,
+    ) -> Result<ResponseBody> {
+        let response = UnixStream::connect(&self.socket_path)
+            .and_then(|mut stream| {
+                write_line(
+                    &mut stream,
+                    &Request {
+                        version: PROTOCOL_VERSION,
+                        action,
+                        names,
+                        parallel,
+                    },
+                )?;
+                read_line::<Response>(&stream)
+            })
+            .map_err(|e| Error::Daemon(format!("通信失败 {}: {}", self.socket_path, e)))?;
+        match response.body {
+            ResponseBody::Error(e) => Err(Error::Daemon(e)),
+            body => Ok(body),
+        }
+    }
+
+    pub fn list(&self, names: Vec<String>) -> Result<Vec<ProcessItem>> {
+        match self.call(Action::List, names)? {
+            ResponseBody::List(items) => Ok(items),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    pub fn start(&self, names: Vec<String>, parallel: usize) -> Result<Vec<(String, StartStatus)>> {
+        match self.call_parallel(Action::Start, names, parallel)? {
+            ResponseBody::Start(results) => Ok(results),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    pub fn stop(&self, names: Vec<String>, parallel: usize) -> Result<Vec<(String, StopStatus)>> {
+        match self.call_parallel(Action::Stop, names, parallel)? {
+            ResponseBody::Stop(results) => Ok(results),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    pub fn restart(&self, names: Vec<String>) -> Result<Vec<(String, StopStatus, StartStatus)>> {
+        match self.call(Action::Restart, names)? {
+            ResponseBody::Restart(results) => Ok(results),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    pub fn reload(&self, names: Vec<String>) -> Result<Vec<(String, ReloadStatus)>> {
+        match self.call(Action::Reload, names)? {
+            ResponseBody::Reload(results) => Ok(results),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    pub fn status(&self, names: Vec<String>, parallel: usize) -> Result<Vec<ProcessStatus>> {
+        match self.call_parallel(Action::Status, names, parallel)? {
+            ResponseBody::Status(statuses) => Ok(statuses),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    pub fn logs(&self, names: Vec<String>) -> Result<Vec<(String, String)>> {
+        match self.call(Action::Logs, names)? {
+            ResponseBody::Logs(paths) => Ok(paths),
+            _ => Err(unexpected_response()),
+        }
+    }
+}
+
+fn unexpected_response() -> Error {
+    Error::Daemon("返回了不匹配的响应".to_string())
+}
@@ -1,46 +1,506 @@
+pub mod cgroup;
+pub mod deps;
+pub mod dotenv;
+pub mod error;
+pub mod group;
+pub mod health;
+pub mod ipc;
+pub mod limits;
+pub mod logs;
+pub mod migrate;
+pub mod parallel;
+pub mod persist;
 pub mod process;
+pub mod profile;
+pub mod state;
+pub mod supervisor;
+pub mod template;
 
-use clap::{arg, Args, Command};
+use cgroup::{Cgroup, CgroupConfig};
+use clap::{Args, ValueEnum};
+pub use error::{Error, Result};
+use group::ProcessGroup;
+use health::{HealthCheck, HealthStatus};
+use limits::ResourceLimits;
+use logs::LogRotation;
+use persist::{FileLock, FileStamp};
 use serde::{Deserialize, Serialize};
+use state::{ExitRecord, RunRecord, StateStore};
 use std::{
-    error::Error,
+    collections::BTreeMap,
+    fmt,
     fs::OpenOptions,
-    io::{self, BufWriter, Read, Write},
-    time::SystemTime,
+    io::Read,
+    path::Path,
+    process::Child,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
 };
+use template::TemplateContext;
 
 pub struct ProcessManager {
+    /// 配置目录的绝对路径，即模板变量 `{PM_PATH}`
+    pm_path: String,
     conf_path: String,
+    /// 修改配置时持有的锁文件
+    lock_path: String,
     conf: Conf,
+    /// 读取配置时文件的指纹，保存前用来判断是否被其他 pm 修改过
+    conf_stamp: FileStamp,
+    state: StateStore,
+    /// 启动、停止、查询状态时同时操作的进程数，见 [`ProcessManager::set_parallel`]
+    parallel: usize,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 struct Conf {
+    /// 配置版本，见 [`migrate::CONFIG_VERSION`]
+    #[serde(default)]
+    version: u32,
+    #[serde(default)]
     processes: Vec<ProcessItem>,
+    #[serde(default)]
+    groups: Vec<ProcessGroup>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Conf {
+    /// 检查进程之间、分组与进程之间的引用
+    fn check(&self) -> Result<()> {
+        deps::check(&self.processes)?;
+        group::check(&self.groups, &self.processes)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct ProcessItem {
     pub name: String,
+    #[serde(default)]
     pub tags: Vec<String>,
     pub command: String,
+    #[serde(default)]
     pub process_type: String,
-    pub log_path: String,
+    /// 日志路径，未配置时继承分组的 log_dir，都没有时丢弃输出
+    #[serde(default)]
+    pub log_path: Option<String>,
     /// 检测启动命令
+    #[serde(default)]
     pub detection_start_cmd: String,
+    #[serde(default)]
     pub comment: String,
+    /// pid查询命令，用于停止和查看不是由 pm 启动的进程
+    #[serde(default)]
+    pub pid_search_cmd: String,
+    /// 停止信号
+    #[serde(default = "default_stop_signal")]
+    pub stop_signal: String,
+    /// 停止等待时长（秒），超时后发送 SIGKILL
+    #[serde(default = "default_stop_timeout")]
+    pub stop_timeout: u64,
+    /// 重新加载信号
+    #[serde(default = "default_reload_signal")]
+    pub reload_signal: String,
+    /// 重新加载命令，配置后代替发送重新加载信号
+    #[serde(default)]
+    pub reload_cmd: String,
+    /// 重启策略，由 `pm daemon` 执行，未配置时继承分组的重启策略，都没有时为 never
+    #[serde(default)]
+    pub restart_policy: Option<RestartPolicy>,
+    /// 最大连续重启次数，超过后进入崩溃循环状态不再重启
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// 首次重启前的等待时长（秒），之后每次翻倍
+    #[serde(default = "default_restart_backoff")]
+    pub restart_backoff: u64,
+    /// 启动时追加写入日志，默认清空
+    #[serde(default)]
+    pub log_append: bool,
+    /// 日志轮转
+    #[serde(default)]
+    pub log_rotation: LogRotation,
+    /// 工作目录，未配置时继承分组的工作目录，为空时继承 pm 的工作目录
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// 环境变量，覆盖 env_files 中的同名变量
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// dotenv 格式的环境变量文件，按顺序加载，相对路径相对于 cwd
+    #[serde(default)]
+    pub env_files: Vec<String>,
+    /// 不继承 pm 的环境变量
+    #[serde(default)]
+    pub clear_env: bool,
+    /// 运行的用户，名称或 uid，为空时与 pm 相同
+    #[serde(default)]
+    pub user: String,
+    /// 运行的组，名称或 gid，为空时使用用户的主组
+    #[serde(default)]
+    pub group: String,
+    /// 附加组，为空时使用用户所属的全部组
+    #[serde(default)]
+    pub supplementary_groups: Vec<String>,
+    /// 八进制的文件创建掩码，如 `027`，为空时继承 pm 的掩码
+    #[serde(default)]
+    pub umask: String,
+    /// 资源限制
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// cgroup v2 限制，启用后停止时结束整个进程树
+    #[serde(default)]
+    pub cgroup: CgroupConfig,
+    /// 健康检查，由 `pm status` 和守护进程执行
+    #[serde(default)]
+    pub health: Option<HealthCheck>,
+    /// 依赖的进程，唯一程序名或 `tag:标签`，启动前先启动并等待它们就绪
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+}
+
+/// 重启策略
+#[derive(Serialize, Deserialize, ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// 不重启
+    #[default]
+    Never,
+    /// 退出码非 0 时重启
+    OnFailure,
+    /// 总是重启
+    Always,
+}
+
+impl fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestartPolicy::Never => write!(f, "never"),
+            RestartPolicy::OnFailure => write!(f, "on-failure"),
+            RestartPolicy::Always => write!(f, "always"),
+        }
+    }
+}
+
+fn default_stop_signal() -> String {
+    "SIGTERM".to_string()
+}
+
+fn default_stop_timeout() -> u64 {
+    10
+}
+
+fn default_reload_signal() -> String {
+    "SIGHUP".to_string()
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+fn default_restart_backoff() -> u64 {
+    1
 }
+
 impl ProcessItem {
-    fn format(&self) -> String {
-        format!(
-            "{}\t{}\t{}\t{}\t{}\t{}",
-            self.process_type,
-            self.name,
-            self.tags.join(", "),
-            self.command,
-            self.detection_start_cmd,
-            self.log_path
-        )
+    /// 日志路径，未配置时为空
+    pub fn log_path(&self) -> &str {
+        self.log_path.as_deref().unwrap_or_default()
+    }
+
+    /// 工作目录，未配置时为空
+    pub fn cwd(&self) -> &str {
+        self.cwd.as_deref().unwrap_or_default()
+    }
+
+    /// 重启策略，未配置时为 never
+    pub fn restart_policy(&self) -> RestartPolicy {
+        self.restart_policy.unwrap_or_default()
+    }
+
+    /// 展开 command、log_path、detection_start_cmd、cwd、env、env_files、health 中的模板变量
+    ///
+    /// 先展开 cwd、env_files 和 env，其余字段中的 `${ENV}` 使用进程启动时的环境变量
+    pub fn expand(&self, ctx: &TemplateContext) -> Result<ProcessItem> {
+        let expand_with = |ctx: &TemplateContext, field: &str, value: &str| {
+            ctx.expand(value)
+                .map_err(|e| Error::Invalid(format!("{}: {}", field, e)))
+        };
+        let cwd = self
+            .cwd
+            .as_ref()
+            .map(|it| expand_with(ctx, "cwd", it))
+            .transpose()?;
+        let env_files = self
+            .env_files
+            .iter()
+            .map(|it| expand_with(ctx, "env_files", it))
+            .collect::<Result<Vec<_>>>()?;
+        // 文件错误在启动时报告，这里无法读取时找不到的变量原样保留
+        let mut file_env = vec![];
+        let mut env_complete = true;
+        for env_file in &env_files {
+            let path = Path::new(cwd.as_deref().unwrap_or_default()).join(env_file);
+            match dotenv::read_env_file(&path.to_string_lossy()) {
+                Ok(vars) => file_env.extend(vars),
+                Err(_) => env_complete = false,
+            }
+        }
+        let env_ctx = ctx
+            .clone()
+            .with_env(file_env, !self.clear_env, env_complete);
+        let env: BTreeMap<String, String> = self
+            .env
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), expand_with(&env_ctx, &format!("env.{}", k), v)?)))
+            .collect::<Result<_>>()?;
+        let ctx = &env_ctx.with_env(env.clone(), !self.clear_env, env_complete);
+
+        let expand = |field: &str, value: &str| expand_with(ctx, field, value);
+        Ok(ProcessItem {
+            command: expand("command", &self.command)?,
+            log_path: self
+                .log_path
+                .as_ref()
+                .map(|it| expand("log_path", it))
+                .transpose()?,
+            detection_start_cmd: expand("detection_start_cmd", &self.detection_start_cmd)?,
+            cwd,
+            env,
+            env_files,
+            health: self
+                .health
+                .as_ref()
+                .map(|it| it.expand(ctx))
+                .transpose()
+                .map_err(|e| Error::Invalid(format!("health: {}", e)))?,
+            ..self.clone()
+        })
+    }
+
+    /// 启动参数，依次读取 env_files 后合并 env，应在展开模板变量后调用
+    pub fn spawn_options(&self) -> Result<process::SpawnOptions> {
+        let mut env = vec![];
+        for env_file in &self.env_files {
+            let path = Path::new(self.cwd()).join(env_file);
+            env.extend(dotenv::read_env_file(&path.to_string_lossy())?);
+        }
+        env.extend(self.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        Ok(process::SpawnOptions {
+            log_path: self.log_path().to_string(),
+            log_append: self.log_append,
+            log_rotation: self.log_rotation.clone(),
+            cwd: self.cwd().to_string(),
+            clear_env: self.clear_env,
+            env,
+            credentials: process::Credentials::lookup(
+                &self.user,
+                &self.group,
+                &self.supplementary_groups,
+            )?,
+            umask: process::parse_umask(&self.umask)?,
+            limits: self.limits.clone(),
+            cgroup: None,
+        })
+    }
+
+    /// 检查配置是否有效：必填字段、信号名称、用户和组、资源限制、模板变量
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(Error::Invalid("唯一程序名不能为空".to_string()));
+        }
+        if self.command.trim().is_empty() {
+            return Err(Error::Invalid(format!("{}: 启动命令不能为空", self.name)));
+        }
+        process::parse_signal(&self.stop_signal)
+            .map_err(|e| Error::Invalid(format!("{}: stop_signal: {}", self.name, e)))?;
+        process::parse_signal(&self.reload_signal)
+            .map_err(|e| Error::Invalid(format!("{}: reload_signal: {}", self.name, e)))?;
+        // 用户和组必须存在，是否有权限切换在启动时检查
+        process::Credentials::lookup(&self.user, &self.group, &self.supplementary_groups)
+            .map_err(|e| Error::Invalid(format!("{}: {}", self.name, e)))?;
+        process::parse_umask(&self.umask)
+            .map_err(|e| Error::Invalid(format!("{}: {}", self.name, e)))?;
+        self.limits
+            .prepare()
+            .map_err(|e| Error::Invalid(format!("{}: {}", self.name, e)))?;
+        self.cgroup
+            .validate()
+            .map_err(|e| Error::Invalid(format!("{}: {}", self.name, e)))?;
+        if let Some(health) = &self.health {
+            health
+                .validate()
+                .map_err(|e| Error::Invalid(format!("{}: {}", self.name, e)))?;
+        }
+        // 用占位的上下文展开一次，提前发现未知变量
+        self.expand(&TemplateContext::new(
+            &self.name,
+            &self.process_type,
+            "",
+            "",
+            None,
+        ))?;
+        Ok(())
+    }
+}
+
+impl Default for ProcessItem {
+    fn default() -> Self {
+        ProcessItem {
+            name: String::new(),
+            tags: vec![],
+            command: String::new(),
+            process_type: String::new(),
+            log_path: None,
+            detection_start_cmd: String::new(),
+            comment: String::new(),
+            pid_search_cmd: String::new(),
+            stop_signal: default_stop_signal(),
+            stop_timeout: default_stop_timeout(),
+            reload_signal: default_reload_signal(),
+            reload_cmd: String::new(),
+            restart_policy: None,
+            max_retries: default_max_retries(),
+            restart_backoff: default_restart_backoff(),
+            log_append: false,
+            log_rotation: LogRotation::default(),
+            cwd: None,
+            env: BTreeMap::new(),
+            env_files: vec![],
+            clear_env: false,
+            user: String::new(),
+            group: String::new(),
+            supplementary_groups: vec![],
+            umask: String::new(),
+            limits: ResourceLimits::default(),
+            cgroup: CgroupConfig::default(),
+            health: None,
+            depends_on: vec![],
+        }
+    }
+}
+
+/// 进程当前状态
+#[derive(Serialize, Deserialize)]
+pub struct ProcessStatus {
+    pub name: String,
+    /// 正在运行的pid
+    pub pids: Vec<u32>,
+    /// 每个pid的运行指标
+    pub stats: Vec<process::ProcessStats>,
+    /// 最近一次退出记录
+    pub last_exit: Option<ExitRecord>,
+    /// 所在 cgroup 的资源用量
+    #[serde(default)]
+    pub cgroup: Option<cgroup::CgroupStats>,
+    /// 配置了 cgroup 但无法使用或无法写入限制的原因
+    #[serde(default)]
+    pub cgroup_warning: Option<String>,
+    /// 健康检查结果，没有配置或未运行时为 None
+    #[serde(default)]
+    pub health: Option<HealthStatus>,
+}
+
+impl ProcessStatus {
+    pub fn is_running(&self) -> bool {
+        !self.pids.is_empty()
+    }
+
+    /// 守护进程多次重启失败后放弃重启
+    pub fn is_crash_loop(&self) -> bool {
+        !self.is_running() && self.last_exit.as_ref().is_some_and(|it| it.crash_loop)
+    }
+}
+
+/// 启动结果
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum StartStatus {
+    /// 已启动，附带pid
+    Started(u32),
+    /// 已经在运行
+    AlreadyRunning,
+    /// 启动失败
+    Failed(String),
+}
+
+impl fmt::Display for StartStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartStatus::Started(pid) => write!(f, "已启动(pid:{})", pid),
+            StartStatus::AlreadyRunning => write!(f, "已在运行"),
+            StartStatus::Failed(reason) => write!(f, "启动失败: {}", reason),
+        }
+    }
+}
+
+/// 等待就绪的结果
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ReadyStatus {
+    /// 已就绪，附带从启动到就绪的时长
+    Ready(Duration),
+    /// 没有运行，启动失败或在就绪前退出
+    NotRunning,
+    /// 超时仍未就绪，附带最近一次检查失败的原因
+    Timeout(String),
+}
+
+impl fmt::Display for ReadyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadyStatus::Ready(elapsed) => write!(f, "已就绪({:.1}s)", elapsed.as_secs_f64()),
+            ReadyStatus::NotRunning => write!(f, "未运行"),
+            ReadyStatus::Timeout(reason) => write!(f, "超时未就绪: {}", reason),
+        }
+    }
+}
+
+/// 等待就绪时的检查间隔
+const READY_POLL: Duration = Duration::from_millis(250);
+/// 启动进程前等待依赖就绪的最长时长
+const DEPENDENCY_READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 重新加载结果
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ReloadStatus {
+    /// 已发送重新加载信号
+    Signaled,
+    /// 已执行重新加载命令
+    Executed,
+    /// 没有运行
+    NotRunning,
+    /// 重新加载失败
+    Failed(String),
+}
+
+impl fmt::Display for ReloadStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReloadStatus::Signaled => write!(f, "已发送信号"),
+            ReloadStatus::Executed => write!(f, "已执行重新加载命令"),
+            ReloadStatus::NotRunning => write!(f, "未运行"),
+            ReloadStatus::Failed(reason) => write!(f, "重新加载失败: {}", reason),
+        }
+    }
+}
+
+/// 停止结果
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum StopStatus {
+    /// 收到停止信号后正常退出
+    Stopped,
+    /// 本来就没有运行
+    AlreadyStopped,
+    /// 超时后被 SIGKILL 强制结束
+    Forced,
+    /// 停止失败
+    Failed(String),
+}
+
+impl fmt::Display for StopStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopStatus::Stopped => write!(f, "已停止"),
+            StopStatus::AlreadyStopped => write!(f, "未运行"),
+            StopStatus::Forced => write!(f, "已强制结束"),
+            StopStatus::Failed(reason) => write!(f, "停止失败: {}", reason),
+        }
     }
 }
 
@@ -61,12 +521,75 @@ pub struct SearchArgs {
 }
 
 impl ProcessManager {
-    pub fn new(profile_path: &str) -> Self {
+    pub fn new(profile_path: &str) -> Result<Self> {
         let conf_path = format!("{}/.config.json", profile_path);
-        ProcessManager {
-            conf_path: conf_path.clone(),
-            conf: ProcessManager::load_conf(conf_path.as_str()),
+        let lock_path = format!("{}/.pm.lock", profile_path);
+        let (mut conf, mut conf_stamp, migrated_from) =
+            ProcessManager::load_conf(conf_path.as_str())?;
+        if migrated_from.is_some() {
+            (conf, conf_stamp) = ProcessManager::upgrade_conf(&conf_path, &lock_path)?;
         }
+        Ok(ProcessManager {
+            pm_path: std::path::absolute(profile_path)
+                .map(|it| it.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| profile_path.to_string()),
+            conf_path,
+            lock_path,
+            conf,
+            conf_stamp,
+            state: StateStore::load(profile_path)?,
+            parallel: parallel::DEFAULT_PARALLEL,
+        })
+    }
+
+    /// 设置同时启动、停止、查询状态的进程数，至少为 1，启动和停止仍按依赖顺序
+    pub fn set_parallel(&mut self, parallel: usize) {
+        self.parallel = parallel.max(1);
+    }
+
+    /// 本次加载时发现已退出的进程记录
+    pub fn stale_runs(&self) -> &[(String, RunRecord)] {
+        self.state.stale()
+    }
+
+    /// 进程当前的运行记录
+    pub fn run_record(&self, name: &str) -> Option<&RunRecord> {
+        self.state.get(name)
+    }
+
+    /// 应用分组的默认配置并展开模板变量，进程在运行时使用该次运行的 run_id 和启动日期
+    pub fn expand(&self, ele: &ProcessItem) -> Result<ProcessItem> {
+        ProcessManager::expand_for_run(
+            &self.pm_path,
+            &self.effective(ele),
+            self.state.get(&ele.name),
+        )
+    }
+
+    /// 应用所在分组的默认配置后的进程配置
+    pub fn effective(&self, ele: &ProcessItem) -> ProcessItem {
+        group::effective(&self.conf.groups, ele)
+    }
+
+    fn expand_for_run(
+        pm_path: &str,
+        ele: &ProcessItem,
+        run: Option<&RunRecord>,
+    ) -> Result<ProcessItem> {
+        let date = run
+            .and_then(|it| chrono::DateTime::from_timestamp(it.started_at as i64, 0))
+            .map(|it| it.with_timezone(&chrono::Local))
+            .unwrap_or_else(chrono::Local::now)
+            .format("%Y-%m-%d")
+            .to_string();
+        let ctx = TemplateContext::new(
+            &ele.name,
+            &ele.process_type,
+            pm_path,
+            &date,
+            run.map(|it| it.run_id.as_str()),
+        );
+        ele.expand(&ctx)
     }
 
     pub fn list(&self, search_args: SearchArgs) -> Vec<&ProcessItem> {
@@ -114,32 +637,157 @@ impl ProcessManager {
             filter = filter && process_type.contains(&ele.process_type);
         }
         if let Some(ref log_path) = search_args.log_path {
-            filter = filter && log_path.contains(&ele.log_path);
+            filter = filter && log_path.iter().any(|it| it == ele.log_path());
         }
         if let Some(ref detection_start_cmd) = search_args.detection_start_cmd {
-            filter = filter && detection_start_cmd.contains(&ele.log_path);
+            filter = filter && detection_start_cmd.iter().any(|it| it == ele.log_path());
         }
 
         filter
     }
 
-    pub fn add(&mut self, process_item: ProcessItem) {
-        self.conf.processes.push(process_item);
+    pub fn add(&mut self, process_item: ProcessItem) -> Result<()> {
+        process_item.validate()?;
+        self.modify(|conf| {
+            if conf.processes.iter().any(|it| it.name == process_item.name) {
+                return Err(Error::DuplicateName(process_item.name));
+            }
+            conf.processes.push(process_item);
+            Ok(())
+        })
+    }
+
+    /// 全部分组
+    pub fn groups(&self) -> &[ProcessGroup] {
+        &self.conf.groups
+    }
+
+    pub fn get_group(&self, name: &str) -> Option<&ProcessGroup> {
+        self.conf.groups.iter().find(|it| it.name == name)
+    }
+
+    /// 分组成员的唯一程序名，按配置中进程的顺序
+    pub fn group_members(&self, name: &str) -> Result<Vec<String>> {
+        let group = self
+            .get_group(name)
+            .ok_or_else(|| Error::Invalid(format!("未找到分组：{}", name)))?;
+        Ok(self
+            .conf
+            .processes
+            .iter()
+            .filter(|it| group.members.contains(&it.name))
+            .map(|it| it.name.clone())
+            .collect())
+    }
 
-        self.rewrite();
+    pub fn add_group(&mut self, group: ProcessGroup) -> Result<()> {
+        group.validate()?;
+        self.modify(|conf| {
+            conf.groups.push(group);
+            Ok(())
+        })
     }
 
-    fn load_conf(conf_path: &str) -> Conf {
+    /// 用新的配置替换分组，读取配置后该分组被其他 pm 修改过时返回 [`Error::Conflict`]
+    pub fn update_group(&mut self, name: &str, group: ProcessGroup) -> Result<()> {
+        group.validate()?;
+        let original = self.get_group(name).cloned();
+        let conf_path = self.conf_path.clone();
+        self.modify(|conf| {
+            let ele = conf
+                .groups
+                .iter_mut()
+                .find(|it| it.name == name)
+                .ok_or_else(|| Error::Invalid(format!("未找到分组：{}", name)))?;
+            if original.as_ref() != Some(ele) {
+                return Err(Error::Conflict {
+                    path: conf_path,
+                    name: name.to_string(),
+                });
+            }
+            *ele = group;
+            Ok(())
+        })
+    }
+
+    /// 删除分组，不删除成员
+    pub fn remove_group(&mut self, name: &str) -> Result<()> {
+        self.modify(|conf| {
+            let len = conf.groups.len();
+            conf.groups.retain(|it| it.name != name);
+            if conf.groups.len() == len {
+                return Err(Error::Invalid(format!("未找到分组：{}", name)));
+            }
+            Ok(())
+        })
+    }
+
+    /// 按唯一程序名查找
+    pub fn get(&self, name: &str) -> Option<&ProcessItem> {
+        self.conf.processes.iter().find(|it| it.name == name)
+    }
+
+    /// 用新的配置替换 `name` 对应的进程，改名时同时迁移运行状态
+    ///
+    /// 读取配置后该进程被其他 pm 修改过时返回 [`Error::Conflict`]，不会覆盖别人的修改
+    pub fn update(&mut self, name: &str, process_item: ProcessItem) -> Result<()> {
+        process_item.validate()?;
+        let new_name = process_item.name.clone();
+        let original = self.get(name).cloned();
+        let conf_path = self.conf_path.clone();
+        self.modify(|conf| {
+            if process_item.name != name
+                && conf.processes.iter().any(|it| it.name == process_item.name)
+            {
+                return Err(Error::DuplicateName(process_item.name));
+            }
+
+            let ele = conf
+                .processes
+                .iter_mut()
+                .find(|it| it.name == name)
+                .ok_or_else(|| Error::NotFound(name.to_string()))?;
+            if original.as_ref() != Some(ele) {
+                return Err(Error::Conflict {
+                    path: conf_path,
+                    name: name.to_string(),
+                });
+            }
+            *ele = process_item;
+            // 改名时按名称引用它的依赖和分组成员跟随新名称
+            if new_name != name {
+                let references = conf
+                    .processes
+                    .iter_mut()
+                    .flat_map(|it| it.depends_on.iter_mut())
+                    .chain(conf.groups.iter_mut().flat_map(|it| it.members.iter_mut()));
+                for reference in references.filter(|it| *it == name) {
+                    *reference = new_name.clone();
+                }
+            }
+            Ok(())
+        })?;
+
+        if new_name != name {
+            self.state.rename(name, &new_name)?;
+        }
+        Ok(())
+    }
+
+    /// 读取配置，旧版本的配置在内存中升级到当前版本，同时返回升级前的版本
+    fn load_conf(conf_path: &str) -> Result<(Conf, FileStamp, Option<u32>)> {
         // 检查文件是否存在
         if !std::path::Path::new(conf_path).exists() {
             println!("File does not exist. Creating file with initial content.");
-            let initial_content = r#"{
+            let initial_content = format!(
+                r#"{{
+                "version": {},
                 "processes": []
-            }"#;
-            // 创建文件并写入初始值
-            std::fs::File::create(conf_path)
-                .and_then(|mut file| file.write_all(initial_content.as_bytes()))
-                .expect("创建初始配置文件失败");
+            }}"#,
+                migrate::CONFIG_VERSION
+            );
+            // 创建文件并写入初始值，同时创建时以先创建的为准
+            persist::create_atomic(conf_path, initial_content.as_bytes())?;
         }
 
         let data = OpenOptions::new()
@@ -150,9 +798,25 @@ impl ProcessManager {
                 conf_file.read_to_string(&mut data)?;
                 Ok(data)
             })
-            .expect("读取配置文件失败");
-        let config: Conf = json5::from_str(&data).expect("转换配置错误");
-        config
+            .map_err(|e| Error::io(conf_path, e))?;
+        let stamp = FileStamp::new(conf_path, data.as_bytes());
+
+        let mut value = json5::from_str::<serde_json::Value>(&data)
+            .map_err(|e| Error::config_parse(conf_path, e))?;
+        let migrated_from = migrate::migrate(&mut value)?;
+        let conf: Conf = match migrated_from {
+            // 直接从文本反序列化，出错时能定位到行列
+            None => json5::from_str(&data).map_err(|e| Error::config_parse(conf_path, e))?,
+            Some(_) => serde_json::from_value(value).map_err(|e| Error::ConfigParse {
+                path: conf_path.to_string(),
+                line: None,
+                column: None,
+                message: e.to_string(),
+            })?,
+        };
+        conf.check()
+            .map_err(|e| Error::Invalid(format!("{}: {}", conf_path, e)))?;
+        Ok((conf, stamp, migrated_from))
         /*
             // 反序列化 JSON5
         let config: Config = json5::from_str(json5_data)?;
@@ -164,61 +828,737 @@ impl ProcessManager {
              */
     }
 
-    fn rewrite(&self) {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&self.conf_path)
-            .expect(&format!("打开配置文件失败：{}", self.conf_path));
+    /// 在配置锁内把旧版本的配置文件升级到当前版本，原文件备份为 `.config.json.v<版本>.bak`
+    fn upgrade_conf(conf_path: &str, lock_path: &str) -> Result<(Conf, FileStamp)> {
+        let _lock = FileLock::acquire(lock_path)?;
+        // 加锁后重新读取，可能已经被其他 pm 升级
+        let (conf, stamp, migrated_from) = ProcessManager::load_conf(conf_path)?;
+        match migrated_from {
+            Some(version) => {
+                let backup_path = format!("{}.v{}.bak", conf_path, version);
+                let stamp = ProcessManager::write_conf(conf_path, &conf, &backup_path)?;
+                Ok((conf, stamp))
+            }
+            None => Ok((conf, stamp)),
+        }
+    }
+
+    /// 在配置锁内重新读取、修改并保存配置
+    ///
+    /// 读取后配置文件被其他 pm 修改过时，在最新的配置上执行修改，避免丢失别人的修改
+    fn modify<F>(&mut self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Conf) -> Result<()>,
+    {
+        let _lock = FileLock::acquire(&self.lock_path)?;
+        let (conf, stamp, _) = ProcessManager::load_conf(&self.conf_path)?;
+        if stamp != self.conf_stamp {
+            self.conf = conf;
+            self.conf_stamp = stamp;
+        }
+
+        // 修改后的依赖关系有误时不保存，也不改变内存中的配置
+        let mut conf = self.conf.clone();
+        f(&mut conf)?;
+        conf.check()?;
+        self.conf = conf;
+        self.rewrite()
+    }
+
+    /// 备份当前配置到 `.config.json.bak` 后原子地写入新配置，调用方需持有配置锁
+    fn rewrite(&mut self) -> Result<()> {
+        let backup_path = format!("{}.bak", self.conf_path);
+        self.conf_stamp = ProcessManager::write_conf(&self.conf_path, &self.conf, &backup_path)?;
+        Ok(())
+    }
+
+    /// 把原配置复制到 `backup_path` 后原子地写入新配置
+    fn write_conf(conf_path: &str, conf: &Conf, backup_path: &str) -> Result<FileStamp> {
+        let serialized =
+            json5::to_string(conf).map_err(|e| Error::Invalid(format!("序列化配置失败: {}", e)))?;
+
+        std::fs::copy(conf_path, backup_path).map_err(|e| Error::io(backup_path, e))?;
+        persist::write_atomic(conf_path, serialized.as_bytes())?;
+        Ok(FileStamp::new(conf_path, serialized.as_bytes()))
+    }
+
+    pub fn remove(&mut self, names: Vec<String>) -> Result<()> {
+        self.modify(|conf| {
+            conf.processes.retain(|it| !names.contains(&it.name));
+            for group in conf.groups.iter_mut() {
+                group.members.retain(|it| !names.contains(it));
+            }
+            Ok(())
+        })
+    }
+
+    /// 按依赖顺序启动 `collect` 及其依赖的进程，每个进程启动前等待它依赖的进程就绪
+    ///
+    /// 同时最多启动 `parallel` 个进程，结果按启动顺序返回
+    pub fn start(&mut self, collect: Vec<String>) -> Vec<(String, StartStatus)> {
+        self.spawn_all(&collect)
+            .into_iter()
+            .map(|(name, status, _)| (name, status))
+            .collect()
+    }
+
+    /// 并发启动并返回子进程句柄，供需要回收子进程的守护进程使用
+    fn spawn_all(&mut self, names: &[String]) -> Vec<(String, StartStatus, Option<Child>)> {
+        let order = deps::start_order(&self.conf.processes, names);
+        self.spawn_ordered(&order)
+    }
+
+    /// 按 `order` 的顺序启动，每个进程启动前等待它依赖的进程就绪
+    fn spawn_ordered(&mut self, order: &[String]) -> Vec<(String, StartStatus, Option<Child>)> {
+        let conf = &self.conf;
+        let items = order
+            .iter()
+            .filter_map(|name| conf.processes.iter().find(|it| &it.name == name))
+            .map(|ele| group::effective(&conf.groups, ele))
+            .collect::<Vec<_>>();
+        // 依赖的进程启动完成后才开始，之后再等待它们就绪
+        let blockers = items
+            .iter()
+            .map(|ele| {
+                deps::dependencies(&conf.processes, ele)
+                    .into_iter()
+                    .filter_map(|dep| items.iter().position(|it| it.name == dep.name))
+                    .collect()
+            })
+            .collect::<Vec<_>>();
 
-        let serialized = json5::to_string(&self.conf).expect("序列化配置失败");
+        let pm_path = &self.pm_path;
+        let state = Mutex::new(&mut self.state);
+        let results = parallel::run(items.len(), self.parallel, &blockers, |i| {
+            let ele = &items[i];
+            match ProcessManager::wait_dependencies(pm_path, conf, ele) {
+                Ok(_) => ProcessManager::spawn_shared(&state, pm_path, ele),
+                Err(e) => (StartStatus::Failed(e), None),
+            }
+        });
+        items
+            .into_iter()
+            .zip(results)
+            .map(|(ele, (status, child))| (ele.name, status, child))
+            .collect()
+    }
 
-        file.write_all(serialized.as_bytes())
-            .and_then(|_| file.flush())
-            .expect(&format!("写入配置失败: {}", serialized));
+    /// 等待 `ele` 依赖的进程就绪，返回未就绪的依赖
+    fn wait_dependencies(
+        pm_path: &str,
+        conf: &Conf,
+        ele: &ProcessItem,
+    ) -> std::result::Result<(), String> {
+        let deps = deps::dependencies(&conf.processes, ele)
+            .into_iter()
+            .map(|it| group::effective(&conf.groups, it))
+            .collect::<Vec<_>>();
+        if deps.is_empty() {
+            return Ok(());
+        }
+        let not_ready = ProcessManager::poll_ready(pm_path, &deps, DEPENDENCY_READY_TIMEOUT)
+            .into_iter()
+            .filter(|(_, status)| !matches!(status, ReadyStatus::Ready(_)))
+            .map(|(name, status)| format!("{} {}", name, status))
+            .collect::<Vec<_>>();
+        if not_ready.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("依赖未就绪: {}", not_ready.join(", ")))
+        }
     }
 
-    pub fn remove(&mut self, names: Vec<String>) {
-        self.conf.processes.retain(|it| !names.contains(&it.name));
+    /// 依赖的进程是否都已就绪，不等待
+    fn dependencies_ready(
+        pm_path: &str,
+        state: &StateStore,
+        conf: &Conf,
+        ele: &ProcessItem,
+    ) -> bool {
+        deps::dependencies(&conf.processes, ele)
+            .into_iter()
+            .map(|it| group::effective(&conf.groups, it))
+            .all(|dep| {
+                ProcessManager::tree_pids(state, &dep).is_ok_and(|it| !it.is_empty())
+                    && ProcessManager::readiness(pm_path, state, &dep).is_ok()
+            })
+    }
 
-        self.rewrite();
+    /// `ele` 直接依赖的进程
+    pub fn dependencies(&self, ele: &ProcessItem) -> Vec<&ProcessItem> {
+        deps::dependencies(&self.conf.processes, ele)
+    }
+
+    /// 启动进程并返回子进程句柄，供需要回收子进程的守护进程使用
+    fn spawn_one(
+        state: &mut StateStore,
+        pm_path: &str,
+        ele: &ProcessItem,
+    ) -> (StartStatus, Option<Child>) {
+        ProcessManager::spawn_shared(&Mutex::new(state), pm_path, ele)
+    }
+
+    /// 可以在多个线程中同时调用，只在读写运行状态时加锁
+    fn spawn_shared(
+        state: &Mutex<&mut StateStore>,
+        pm_path: &str,
+        ele: &ProcessItem,
+    ) -> (StartStatus, Option<Child>) {
+        // 检测是否已启动
+        if state.lock().unwrap().get(&ele.name).is_some() {
+            return (StartStatus::AlreadyRunning, None);
+        }
+        let (child, record) = match ProcessManager::launch(pm_path, ele) {
+            Ok(launched) => launched,
+            Err(status) => return (status, None),
+        };
+        let pid = child.id();
+        match state.lock().unwrap().record(&ele.name, record) {
+            Ok(_) => (StartStatus::Started(pid), Some(child)),
+            Err(e) => (
+                StartStatus::Failed(format!("保存运行状态失败: {}", e)),
+                Some(child),
+            ),
+        }
+    }
+
+    /// 检测并启动进程，不读写运行状态，失败时返回对应的启动结果
+    fn launch(
+        pm_path: &str,
+        ele: &ProcessItem,
+    ) -> std::result::Result<(Child, RunRecord), StartStatus> {
+        let run_id = state::new_run_id();
+        let ctx = TemplateContext::new(
+            &ele.name,
+            &ele.process_type,
+            pm_path,
+            &chrono::Local::now().format("%Y-%m-%d").to_string(),
+            Some(&run_id),
+        );
+        let ele = match ele.expand(&ctx) {
+            Ok(ele) => ele,
+            Err(e) => return Err(StartStatus::Failed(format!("模板展开失败: {}", e))),
+        };
+
+        match process::is_started(&ele.detection_start_cmd) {
+            Ok(true) => return Err(StartStatus::AlreadyRunning),
+            Ok(false) => {}
+            Err(e) => return Err(StartStatus::Failed(e.to_string())),
+        }
+
+        let mut options = match ele.spawn_options() {
+            Ok(options) => options,
+            Err(e) => return Err(StartStatus::Failed(e.to_string())),
+        };
+        // cgroup 不可用时退回到只跟踪主进程
+        let (cgroup, cgroup_warning) = if ele.cgroup.is_enabled() {
+            match Cgroup::create(&ele.name, &run_id, &ele.cgroup) {
+                Ok((cgroup, warnings)) if warnings.is_empty() => (Some(cgroup), None),
+                Ok((cgroup, warnings)) => (Some(cgroup), Some(warnings.join("; "))),
+                Err(e) => (None, Some(e.to_string())),
+            }
+        } else {
+            (None, None)
+        };
+        options.cgroup = cgroup.as_ref().map(|it| it.path().to_string());
+        let child = match process::spawn_detached(&ele.command, &options) {
+            Ok(child) => child,
+            Err(e) => {
+                if let Some(cgroup) = cgroup {
+                    cgroup.remove();
+                }
+                return Err(StartStatus::Failed(e.to_string()));
+            }
+        };
+        let mut record = RunRecord::with_run_id(run_id, child.id(), &ele.command);
+        record.cgroup = options.cgroup;
+        record.cgroup_warning = cgroup_warning;
+        Ok((child, record))
+    }
+
+    /// 按依赖的反向顺序停止，依赖其他进程的先停止
+    ///
+    /// 同时最多停止 `parallel` 个进程，依赖它的进程都结束后才停止被依赖的进程
+    pub fn stop(&mut self, names: Vec<String>) -> Vec<(String, StopStatus)> {
+        let processes = &self.conf.processes;
+        let items = deps::stop_order(processes, &names)
+            .iter()
+            .filter_map(|name| processes.iter().find(|it| &it.name == name))
+            .map(|ele| self.effective(ele))
+            .collect::<Vec<_>>();
+        let blockers = items
+            .iter()
+            .map(|ele| {
+                items
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, it)| {
+                        deps::dependencies(processes, it)
+                            .iter()
+                            .any(|dep| dep.name == ele.name)
+                    })
+                    .map(|(i, _)| i)
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+
+        let state = &self.state;
+        let results = parallel::run(items.len(), self.parallel, &blockers, |i| {
+            ProcessManager::terminate_one(state, &items[i])
+        });
+        // 结束进程可能需要等待，全部结束后再依次保存运行状态
+        items
+            .into_iter()
+            .zip(results)
+            .map(|(ele, status)| {
+                let status = ProcessManager::record_stopped(&mut self.state, &ele, status);
+                (ele.name, status)
+            })
+            .collect()
+    }
+
+    /// 结束进程后记录为主动停止，结束失败时不修改运行状态
+    fn record_stopped(state: &mut StateStore, ele: &ProcessItem, status: StopStatus) -> StopStatus {
+        if let StopStatus::Failed(_) = status {
+            return status;
+        }
+        match state.record_stop(&ele.name) {
+            Ok(_) => status,
+            Err(e) => StopStatus::Failed(format!("保存运行状态失败: {}", e)),
+        }
+    }
+
+    /// 结束进程树，不修改运行状态
+    fn terminate_one(state: &StateStore, ele: &ProcessItem) -> StopStatus {
+        let pids = match ProcessManager::tree_pids(state, ele) {
+            Ok(pids) => pids,
+            Err(e) => return StopStatus::Failed(format!("查询pid失败: {}", e)),
+        };
+        if pids.is_empty() {
+            return StopStatus::AlreadyStopped;
+        }
+
+        let signal = match process::parse_signal(&ele.stop_signal) {
+            Ok(signal) => signal,
+            Err(e) => return StopStatus::Failed(e.to_string()),
+        };
+
+        // 结束整个进程树：优先使用 cgroup，其次是启动时创建的会话和子孙进程
+        let grace = Duration::from_secs(ele.stop_timeout);
+        let result = match ProcessManager::run_cgroup(state, ele) {
+            Some(cgroup) => cgroup.terminate(signal, grace),
+            None => ProcessManager::root_pids(state, ele).and_then(|roots| {
+                process::terminate_tree(&roots, state.get(&ele.name).is_some(), signal, grace)
+            }),
+        };
+        match result {
+            Ok(false) => StopStatus::Stopped,
+            Ok(true) => StopStatus::Forced,
+            Err(e) => StopStatus::Failed(e.to_string()),
+        }
     }
 
-    pub fn start(&self, collect: Vec<String>) {
-        let filter = self
+    /// 先停止再启动，停止失败时不会启动
+    ///
+    /// 按依赖顺序停止后再按依赖顺序启动，启动前等待依赖的进程就绪
+    pub fn restart(&mut self, names: Vec<String>) -> Vec<(String, StopStatus, StartStatus)> {
+        self.respawn_all(&names)
+            .into_iter()
+            .map(|(name, stop_status, start_status, _)| (name, stop_status, start_status))
+            .collect()
+    }
+
+    /// 重启并返回子进程句柄，供需要回收子进程的守护进程使用
+    pub(crate) fn respawn_all(
+        &mut self,
+        names: &[String],
+    ) -> Vec<(String, StopStatus, StartStatus, Option<Child>)> {
+        let mut order = deps::start_order(&self.conf.processes, names);
+        order.retain(|name| names.contains(name));
+        let stopped = self.stop(names.to_vec());
+        let restartable = order
+            .iter()
+            .filter(|name| {
+                stopped
+                    .iter()
+                    .any(|(it, status)| it == *name && !matches!(status, StopStatus::Failed(_)))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut started = self.spawn_ordered(&restartable);
+
+        order
+            .into_iter()
+            .filter_map(|name| {
+                let (_, stop_status) = stopped.iter().find(|(it, _)| it == &name)?.clone();
+                let (start_status, child) = match started.iter().position(|(it, ..)| it == &name) {
+                    Some(i) => {
+                        let (_, status, child) = started.remove(i);
+                        (status, child)
+                    }
+                    None => (
+                        StartStatus::Failed("停止失败，未重新启动".to_string()),
+                        None,
+                    ),
+                };
+                Some((name, stop_status, start_status, child))
+            })
+            .collect()
+    }
+
+    pub fn reload(&self, names: Vec<String>) -> Vec<(String, ReloadStatus)> {
+        self.conf
+            .processes
+            .iter()
+            .filter(|it| names.contains(&it.name))
+            .map(|ele| {
+                (
+                    ele.name.clone(),
+                    ProcessManager::reload_one(&self.state, &self.effective(ele)),
+                )
+            })
+            .collect()
+    }
+
+    fn reload_one(state: &StateStore, ele: &ProcessItem) -> ReloadStatus {
+        let pids = match ProcessManager::running_pids(state, ele) {
+            Ok(pids) => pids,
+            Err(e) => return ReloadStatus::Failed(format!("查询pid失败: {}", e)),
+        };
+        if pids.is_empty() {
+            return ReloadStatus::NotRunning;
+        }
+
+        if !ele.reload_cmd.is_empty() {
+            return match process::run_reload_cmd(&ele.reload_cmd, &pids) {
+                Ok(_) => ReloadStatus::Executed,
+                Err(e) => ReloadStatus::Failed(e.to_string()),
+            };
+        }
+
+        let signal = match process::parse_signal(&ele.reload_signal) {
+            Ok(signal) => signal,
+            Err(e) => return ReloadStatus::Failed(e.to_string()),
+        };
+        match process::signal_all(&pids, signal) {
+            Ok(_) => ReloadStatus::Signaled,
+            Err(e) => ReloadStatus::Failed(e.to_string()),
+        }
+    }
+
+    /// 本次运行所在的 cgroup，没有使用 cgroup 或已被删除时返回 None
+    fn run_cgroup(state: &StateStore, ele: &ProcessItem) -> Option<Cgroup> {
+        state
+            .get(&ele.name)
+            .and_then(|it| it.cgroup.as_deref())
+            .map(Cgroup::open)
+            .filter(|it| it.exists())
+    }
+
+    /// 进程树的根：启动时记录的pid（可能已退出），非 pm 启动的进程只通过pid查询命令查找
+    fn root_pids(state: &StateStore, ele: &ProcessItem) -> Result<Vec<u32>> {
+        match state.get(&ele.name) {
+            Some(record) => Ok(vec![record.pid]),
+            None => process::find_pids(&ele.pid_search_cmd),
+        }
+    }
+
+    /// 正在运行的主进程pid，重新加载时只通知主进程
+    fn running_pids(state: &StateStore, ele: &ProcessItem) -> Result<Vec<u32>> {
+        Ok(ProcessManager::root_pids(state, ele)?
+            .into_iter()
+            .filter(|it| process::is_alive(*it))
+            .collect())
+    }
+
+    /// 整个进程树中正在运行的进程，停止时会结束这些进程
+    fn process_tree(state: &StateStore, ele: &ProcessItem) -> Result<Vec<process::TreeProcess>> {
+        match ProcessManager::run_cgroup(state, ele) {
+            Some(cgroup) => Ok(process::process_tree(&cgroup.pids()?, true)),
+            // 只有 pm 启动的进程才包括所在会话中的其他进程
+            None => Ok(process::process_tree(
+                &ProcessManager::root_pids(state, ele)?,
+                state.get(&ele.name).is_some(),
+            )),
+        }
+    }
+
+    fn tree_pids(state: &StateStore, ele: &ProcessItem) -> Result<Vec<u32>> {
+        Ok(ProcessManager::process_tree(state, ele)?
+            .into_iter()
+            .map(|it| it.pid)
+            .collect())
+    }
+
+    /// 预览停止时会结束的进程
+    pub fn stop_preview(
+        &self,
+        names: Vec<String>,
+    ) -> Vec<(String, Result<Vec<process::TreeProcess>>)> {
+        self.conf
+            .processes
+            .iter()
+            .filter(|it| names.contains(&it.name))
+            .map(|ele| {
+                (
+                    ele.name.clone(),
+                    ProcessManager::process_tree(&self.state, &self.effective(ele)),
+                )
+            })
+            .collect()
+    }
+
+    /// 等待进程就绪，超过 `timeout` 后返回仍未就绪的原因
+    ///
+    /// 配置了健康检查时以检查通过为准，否则以检测启动命令有输出为准，两者都没有配置时运行即就绪。
+    /// 每次检查前重新读取运行状态，进程可能由守护进程启动或重启
+    pub fn wait_ready(
+        &mut self,
+        names: Vec<String>,
+        timeout: Duration,
+    ) -> Vec<(String, ReadyStatus)> {
+        let items = self
             .conf
             .processes
             .iter()
-            .filter(|it| collect.contains(&it.name));
-        for ele in filter {
-            // 检测是否已启动
-            if process::is_started(&ele.detection_start_cmd).expect("检查是否启动命令有误")
-            {
-                continue;
+            .filter(|it| names.contains(&it.name))
+            .map(|it| self.effective(it))
+            .collect::<Vec<_>>();
+        let results = ProcessManager::poll_ready(&self.pm_path, &items, timeout);
+        if let Ok(state) = StateStore::load(&self.pm_path) {
+            self.state = state;
+        }
+        results
+    }
+
+    /// 轮询直到 `items` 都就绪或超时，不依赖当前的运行状态，可以在多个线程中同时调用
+    fn poll_ready(
+        pm_path: &str,
+        items: &[ProcessItem],
+        timeout: Duration,
+    ) -> Vec<(String, ReadyStatus)> {
+        let begin = Instant::now();
+        let deadline = begin + timeout;
+        let mut results: Vec<Option<ReadyStatus>> = vec![None; items.len()];
+
+        loop {
+            let timed_out = Instant::now() >= deadline;
+            match StateStore::load(pm_path) {
+                Ok(state) => {
+                    for (ele, result) in items.iter().zip(results.iter_mut()) {
+                        if result.is_some() {
+                            continue;
+                        }
+                        let running =
+                            ProcessManager::tree_pids(&state, ele).is_ok_and(|it| !it.is_empty());
+                        if !running {
+                            *result = Some(ReadyStatus::NotRunning);
+                            continue;
+                        }
+                        match ProcessManager::readiness(pm_path, &state, ele) {
+                            Ok(_) => {
+                                let elapsed = state
+                                    .get(&ele.name)
+                                    .map_or_else(|| begin.elapsed(), RunRecord::elapsed);
+                                *result = Some(ReadyStatus::Ready(elapsed))
+                            }
+                            Err(reason) if timed_out => {
+                                *result = Some(ReadyStatus::Timeout(reason))
+                            }
+                            Err(_) => {}
+                        }
+                    }
+                }
+                // 运行状态正被其他 pm 写入等情况下稍后重试
+                Err(e) if timed_out => {
+                    for result in results.iter_mut().filter(|it| it.is_none()) {
+                        *result = Some(ReadyStatus::Timeout(e.to_string()));
+                    }
+                }
+                Err(_) => {}
+            }
+            if results.iter().all(|it| it.is_some()) {
+                break;
             }
+            thread::sleep(READY_POLL.min(deadline.saturating_duration_since(Instant::now())));
+        }
+
+        items
+            .iter()
+            .zip(results)
+            .map(|(ele, result)| (ele.name.clone(), result.unwrap_or(ReadyStatus::NotRunning)))
+            .collect()
+    }
 
-            process::swpan(&ele.command, &ele.log_path);
+    /// 检查运行中的进程是否就绪，未就绪时返回原因
+    fn readiness(
+        pm_path: &str,
+        state: &StateStore,
+        ele: &ProcessItem,
+    ) -> std::result::Result<(), String> {
+        let run = state.get(&ele.name);
+        if let Some(health) = ProcessManager::probe_health(pm_path, ele, run) {
+            return match health {
+                HealthStatus::Healthy => Ok(()),
+                HealthStatus::Unhealthy(reason) => Err(reason),
+            };
+        }
+        if ele.detection_start_cmd.is_empty() {
+            return Ok(());
         }
+        let ele = ProcessManager::expand_for_run(pm_path, ele, run).map_err(|e| e.to_string())?;
+        match process::is_started(&ele.detection_start_cmd) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err("检测启动命令没有输出".to_string()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// 执行一次健康检查，没有配置时返回 None
+    fn probe_health(
+        pm_path: &str,
+        ele: &ProcessItem,
+        run: Option<&RunRecord>,
+    ) -> Option<HealthStatus> {
+        ele.health.as_ref()?;
+        let result = match ProcessManager::expand_for_run(pm_path, ele, run) {
+            Ok(ProcessItem {
+                health: Some(health),
+                log_path,
+                ..
+            }) => health.probe(log_path.as_deref().unwrap_or_default()),
+            Ok(_) => return None,
+            Err(e) => Err(e.to_string()),
+        };
+        Some(match result {
+            Ok(_) => HealthStatus::Healthy,
+            Err(e) => HealthStatus::Unhealthy(e),
+        })
+    }
+
+    /// 唯一程序名和对应的日志路径（已展开模板变量）
+    pub fn logs(&self, names: Vec<String>) -> Vec<(String, String)> {
+        self.conf
+            .processes
+            .iter()
+            .filter(|it| names.contains(&it.name))
+            .map(|it| {
+                let log_path = self
+                    .expand(it)
+                    .map(|it| it.log_path().to_string())
+                    .unwrap_or_else(|_| self.effective(it).log_path().to_string());
+                (it.name.clone(), log_path)
+            })
+            .collect()
+    }
+
+    /// 同时最多查询 `parallel` 个进程，所有进程一起采样
+    pub fn status(&self, names: Vec<String>) -> Vec<ProcessStatus> {
+        let items = self
+            .conf
+            .processes
+            .iter()
+            .filter(|it| names.contains(&it.name))
+            .map(|it| self.effective(it))
+            .collect::<Vec<_>>();
+        // 查询 pid 和健康检查都可能需要执行命令
+        let checked = parallel::run(items.len(), self.parallel, &[], |i| {
+            let ele = &items[i];
+            let pids = ProcessManager::tree_pids(&self.state, ele).unwrap_or_default();
+            let health = if pids.is_empty() {
+                None
+            } else {
+                ProcessManager::probe_health(&self.pm_path, ele, self.state.get(&ele.name))
+            };
+            (pids, health)
+        });
+
+        // 所有进程一起采样，只需等待一次 CPU 采样间隔
+        let all_pids = checked
+            .iter()
+            .flat_map(|(pids, _)| pids.iter().copied())
+            .collect::<Vec<_>>();
+        let stats = process::get_process_stats(&all_pids);
+
+        items
+            .iter()
+            .zip(checked)
+            .map(|(ele, (pids, health))| ProcessStatus {
+                name: ele.name.clone(),
+                stats: stats
+                    .iter()
+                    .filter(|it| pids.contains(&it.pid))
+                    .cloned()
+                    .collect(),
+                last_exit: self.state.last_exit(&ele.name).cloned(),
+                cgroup: ProcessManager::run_cgroup(&self.state, ele).and_then(|it| it.stats()),
+                cgroup_warning: self
+                    .state
+                    .get(&ele.name)
+                    .and_then(|it| it.cgroup_warning.clone()),
+                health,
+                pids,
+            })
+            .collect()
     }
 }
 
+#[cfg(test)]
 mod tests {
     use std::env;
 
-    use crate::{ProcessItem, ProcessManager, SearchArgs};
+    use std::time::Duration;
+
+    use crate::{
+        cgroup::CgroupConfig,
+        group::ProcessGroup,
+        health::{HealthCheck, HealthStatus, Probe},
+        ipc::{self, Client},
+        limits::{self, ResourceLimits},
+        logs::{self, LogFollower, LogRotation, RotatingWriter},
+        migrate, process,
+        profile::{Profiles, DEFAULT_PROFILE},
+        state::{RunRecord, StateStore},
+        supervisor::Supervisor,
+        template::TemplateContext,
+        Error, ProcessItem, ProcessManager, ReadyStatus, ReloadStatus, RestartPolicy, SearchArgs,
+        StartStatus, StopStatus,
+    };
+
+    fn temp_profile(name: &str) -> String {
+        let dir = env::temp_dir().join(format!("pm-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().into_owned()
+    }
+
+    fn start_one(state: &mut StateStore, pm_path: &str, ele: &ProcessItem) -> StartStatus {
+        ProcessManager::spawn_one(state, pm_path, ele).0
+    }
+
+    fn stop_one(state: &mut StateStore, ele: &ProcessItem) -> StopStatus {
+        let status = ProcessManager::terminate_one(state, ele);
+        ProcessManager::record_stopped(state, ele, status)
+    }
 
     #[test]
     fn test_load() {
         let cwd = env::current_dir().unwrap();
         println!("Current directory: {}", cwd.display());
 
-        let load_conf = ProcessManager::load_conf("./.config.json");
+        let _load_conf = ProcessManager::load_conf("./.config.json").unwrap();
+    }
+
+    /// 复制仓库中的示例配置，加载时的升级不会改写仓库中的文件
+    fn sample_profile(name: &str) -> String {
+        let profile = temp_profile(name);
+        std::fs::copy(".config.json", format!("{}/.config.json", profile)).unwrap();
+        profile
     }
 
     #[test]
     fn test_list() {
-        let mut pm = ProcessManager::new(".");
+        let pm = ProcessManager::new(&sample_profile("list")).unwrap();
         pm.list(SearchArgs {
             tags: None,
             name: None,
@@ -231,16 +1571,19 @@ mod tests {
 
     #[test]
     fn test_add() {
-        let mut pm = ProcessManager::new(".");
-        pm.add(ProcessItem {
+        let mut pm = ProcessManager::new(&sample_profile("add")).unwrap();
+        // .config.json 中已有 hello
+        let result = pm.add(ProcessItem {
             tags: vec![],
             name: "hello".to_string(),
             command: "java -jar xxx.jar".to_string(),
             process_type: "java".to_string(),
-            log_path: "{PM_PATH}/process_log/{name}".to_string(),
+            log_path: Some("{PM_PATH}/process_log/{name}".to_string()),
             detection_start_cmd: "dscmd".to_string(),
             comment: "备注".to_string(),
+            ..Default::default()
         });
+        assert!(matches!(result, Err(Error::DuplicateName(name)) if name == "hello"));
         pm.list(SearchArgs {
             tags: None,
             name: None,
@@ -250,4 +1593,1316 @@ mod tests {
             log_path: None,
         });
     }
+
+    #[test]
+    fn test_parse_signal() {
+        use nix::sys::signal::Signal;
+
+        assert_eq!(process::parse_signal("").unwrap(), Signal::SIGTERM);
+        assert_eq!(process::parse_signal("SIGHUP").unwrap(), Signal::SIGHUP);
+        assert_eq!(process::parse_signal("int").unwrap(), Signal::SIGINT);
+        assert_eq!(process::parse_signal("9").unwrap(), Signal::SIGKILL);
+        assert!(process::parse_signal("SIGNOPE").is_err());
+    }
+
+    #[test]
+    fn test_stop_escalates_to_kill() {
+        let child = std::process::Command::new("bash")
+            .args(["-c", "trap '' TERM; while true; do sleep 0.1; done"])
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+
+        let item = ProcessItem {
+            name: "stubborn".to_string(),
+            pid_search_cmd: format!("echo {}", pid),
+            stop_timeout: 1,
+            ..Default::default()
+        };
+        // 非 pm 启动的子进程需要回收，否则会一直是僵尸进程
+        let reaper = std::thread::spawn(move || {
+            let mut child = child;
+            child.wait().unwrap();
+        });
+        // 等待 bash 设置好 trap
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        let mut state = StateStore::load(&temp_profile("stop")).unwrap();
+        // 没有运行记录也没有pid查询命令时，不按命令行结束同名的进程
+        let lookalike = ProcessItem {
+            name: "lookalike".to_string(),
+            command: "trap '' TERM; while true; do sleep 0.1; done".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(stop_one(&mut state, &lookalike), StopStatus::AlreadyStopped);
+        assert!(process::is_alive(pid));
+        assert_eq!(stop_one(&mut state, &item), StopStatus::Forced);
+        reaper.join().unwrap();
+        assert_eq!(stop_one(&mut state, &item), StopStatus::AlreadyStopped);
+    }
+
+    #[test]
+    fn test_state_reconcile() {
+        let profile = temp_profile("state");
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+
+        let mut state = StateStore::load(&profile).unwrap();
+        state
+            .record("sleeper", RunRecord::new(child.id(), "sleep 30"))
+            .unwrap();
+        // 记录的启动时间与实际进程不符，视为pid已被复用
+        let mut reused = RunRecord::new(child.id(), "sleep 30");
+        reused.proc_start_ticks = reused.proc_start_ticks.map(|it| it + 1);
+        state.record("reused", reused).unwrap();
+
+        let state = StateStore::load(&profile).unwrap();
+        assert!(state.get("sleeper").is_some());
+        assert!(state.get("reused").is_none());
+        assert_eq!(state.stale().len(), 1);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        let state = StateStore::load(&profile).unwrap();
+        assert!(state.get("sleeper").is_none());
+        assert_eq!(state.stale()[0].0, "sleeper");
+    }
+
+    #[test]
+    fn test_start_many_detached() {
+        let profile = temp_profile("start");
+        let mut state = StateStore::load(&profile).unwrap();
+
+        let items = (0..3)
+            .map(|i| ProcessItem {
+                name: format!("sleeper-{}", i),
+                command: "sleep 30".to_string(),
+                log_path: Some(format!("{}/sleeper-{}.log", profile, i)),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let mut pids = vec![];
+        for item in &items {
+            match start_one(&mut state, &profile, item) {
+                StartStatus::Started(pid) => pids.push(pid),
+                other => panic!("启动失败: {}", other),
+            }
+        }
+        assert_eq!(
+            start_one(&mut state, &profile, &items[0]),
+            StartStatus::AlreadyRunning
+        );
+
+        for pid in &pids {
+            // 子进程在自己的会话中
+            let sid = nix::unistd::getsid(Some(nix::unistd::Pid::from_raw(*pid as i32))).unwrap();
+            assert_eq!(sid.as_raw(), *pid as i32);
+        }
+
+        for item in &items {
+            assert_ne!(stop_one(&mut state, item), StopStatus::AlreadyStopped);
+        }
+    }
+
+    #[test]
+    fn test_restart_and_reload() {
+        let profile = temp_profile("restart");
+        let mut state = StateStore::load(&profile).unwrap();
+        let marker = format!("{}/reloaded", profile);
+        let item = ProcessItem {
+            name: "reloader".to_string(),
+            command: "sleep 30".to_string(),
+            reload_cmd: format!("echo $PM_PID > {}", marker),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            ProcessManager::reload_one(&state, &item),
+            ReloadStatus::NotRunning
+        );
+        let old_pid = match start_one(&mut state, &profile, &item) {
+            StartStatus::Started(pid) => pid,
+            other => panic!("启动失败: {}", other),
+        };
+        assert_eq!(
+            ProcessManager::reload_one(&state, &item),
+            ReloadStatus::Executed
+        );
+        let reloaded = std::fs::read_to_string(&marker).unwrap();
+        assert_eq!(reloaded.trim(), old_pid.to_string());
+
+        assert_eq!(stop_one(&mut state, &item), StopStatus::Stopped);
+        assert!(!process::is_alive(old_pid));
+        match start_one(&mut state, &profile, &item) {
+            StartStatus::Started(pid) => assert_ne!(pid, old_pid),
+            other => panic!("启动失败: {}", other),
+        }
+        stop_one(&mut state, &item);
+    }
+
+    #[test]
+    fn test_supervisor_crash_loop() {
+        let profile = temp_profile("supervisor");
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        pm.add(ProcessItem {
+            name: "crasher".to_string(),
+            command: "exit 3".to_string(),
+            restart_policy: Some(RestartPolicy::OnFailure),
+            max_retries: 2,
+            restart_backoff: 0,
+            ..Default::default()
+        })
+        .unwrap();
+        pm.add(ProcessItem {
+            name: "manual".to_string(),
+            command: "exit 3".to_string(),
+            restart_policy: Some(RestartPolicy::OnFailure),
+            ..Default::default()
+        })
+        .unwrap();
+        pm.start(vec!["crasher".to_string()]);
+
+        let mut supervisor = Supervisor::new(&profile);
+        for _ in 0..50 {
+            supervisor.tick().unwrap();
+            let state = StateStore::load(&profile).unwrap();
+            if state.last_exit("crasher").is_some_and(|it| it.crash_loop) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+
+        let state = StateStore::load(&profile).unwrap();
+        let exit = state.last_exit("crasher").unwrap();
+        assert!(exit.crash_loop);
+        assert_eq!(exit.exit_code, Some(3));
+        // 从未启动过的 on-failure 进程不会被守护进程拉起
+        assert!(state.last_exit("manual").is_none());
+    }
+
+    #[test]
+    fn test_daemon_control_socket() {
+        let profile = temp_profile("socket");
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        pm.add(ProcessItem {
+            name: "served".to_string(),
+            command: "sleep 30".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(Client::connect(&profile).is_none());
+
+        let daemon_profile = profile.clone();
+        std::thread::spawn(move || Supervisor::new(&daemon_profile).run());
+        let client = (0..50)
+            .find_map(|_| {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                Client::connect(&profile)
+            })
+            .expect("守护进程未启动");
+
+        let names = vec!["served".to_string()];
+        assert!(matches!(
+            client.start(names.clone(), 1).unwrap()[0].1,
+            StartStatus::Started(_)
+        ));
+        let statuses = client.status(names.clone(), 1).unwrap();
+        assert!(statuses[0].is_running());
+        assert_eq!(client.list(names.clone()).unwrap()[0].command, "sleep 30");
+        assert_eq!(
+            client.stop(names.clone(), 1).unwrap()[0].1,
+            StopStatus::Stopped
+        );
+
+        // 等待依赖就绪时守护进程照常处理其他请求
+        pm.add(ProcessItem {
+            name: "slow-dep".to_string(),
+            command: "sleep 30".to_string(),
+            health: Some(HealthCheck::new(Probe::Exec {
+                command: "test -f {PM_PATH}/dep.ready".to_string(),
+            })),
+            ..Default::default()
+        })
+        .unwrap();
+        pm.add(ProcessItem {
+            name: "dependent".to_string(),
+            command: "sleep 30".to_string(),
+            depends_on: vec!["slow-dep".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        let start_profile = profile.clone();
+        let starting = std::thread::spawn(move || {
+            let client = Client::connect(&start_profile).unwrap();
+            client.start(vec!["dependent".to_string()], 1).unwrap()
+        });
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        // 连接后不发送请求的客户端也不影响
+        let _idle = std::os::unix::net::UnixStream::connect(ipc::socket_path(&profile)).unwrap();
+        let begin = std::time::Instant::now();
+        client.status(names.clone(), 1).unwrap();
+        assert!(begin.elapsed() < std::time::Duration::from_secs(2));
+        assert!(!starting.is_finished());
+        std::fs::write(format!("{}/dep.ready", profile), "").unwrap();
+        let results = starting.join().unwrap();
+        assert!(results
+            .iter()
+            .all(|(_, status)| matches!(status, StartStatus::Started(_))));
+        client
+            .stop(vec!["slow-dep".to_string(), "dependent".to_string()], 1)
+            .unwrap();
+
+        // 协议版本不一致
+        let mut stream =
+            std::os::unix::net::UnixStream::connect(ipc::socket_path(&profile)).unwrap();
+        use std::io::{BufRead, Write};
+        stream
+            .write_all(b"{\"version\":999,\"action\":\"status\",\"names\":[]}\n")
+            .unwrap();
+        let mut line = String::new();
+        std::io::BufReader::new(&stream)
+            .read_line(&mut line)
+            .unwrap();
+        assert!(line.contains("error"));
+    }
+
+    #[test]
+    fn test_logs_tail_and_follow() {
+        use std::io::Write;
+
+        let profile = temp_profile("logs");
+        let path = format!("{}/app.log", profile);
+        let lines = (0..2000)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+        std::fs::write(&path, lines).unwrap();
+
+        let tail = logs::tail_lines(&path, 3).unwrap();
+        assert_eq!(tail, vec!["line 1997", "line 1998", "line 1999"]);
+
+        let mut follower = LogFollower::new("app", &path);
+        assert!(follower.poll().unwrap().is_empty());
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"appended\npart").unwrap();
+        assert_eq!(follower.poll().unwrap(), vec!["appended"]);
+
+        // 截断
+        std::fs::write(&path, "after truncate\n").unwrap();
+        assert_eq!(follower.poll().unwrap(), vec!["after truncate"]);
+
+        // 轮转：旧文件被改名，新建同名文件
+        std::fs::rename(&path, format!("{}.1", path)).unwrap();
+        std::fs::write(&path, "rotated\n").unwrap();
+        assert_eq!(follower.poll().unwrap(), vec!["rotated"]);
+    }
+
+    #[test]
+    fn test_log_rotation() {
+        let profile = temp_profile("rotation");
+        let path = format!("{}/app.log", profile);
+        let rotation = LogRotation {
+            max_size: 20,
+            keep: 2,
+            compress: true,
+            ..Default::default()
+        };
+
+        let mut writer = RotatingWriter::open(&path, rotation, false).unwrap();
+        for i in 0..4 {
+            writer
+                .write_line(format!("line {:08}\n", i).as_bytes())
+                .unwrap();
+        }
+        writer.flush().unwrap();
+
+        // 每行 14 字节，每个文件只能放一行；只保留 2 个历史日志
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "line 00000003\n");
+        let mut decoder =
+            flate2::read::GzDecoder::new(std::fs::File::open(format!("{}.1.gz", path)).unwrap());
+        let mut rotated = String::new();
+        std::io::Read::read_to_string(&mut decoder, &mut rotated).unwrap();
+        assert_eq!(rotated, "line 00000002\n");
+        assert!(std::path::Path::new(&format!("{}.2.gz", path)).exists());
+        assert!(!std::path::Path::new(&format!("{}.3.gz", path)).exists());
+
+        // 追加模式保留原有内容
+        let writer = RotatingWriter::open(&path, LogRotation::default(), true).unwrap();
+        drop(writer);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "line 00000003\n");
+    }
+
+    #[test]
+    fn test_template_expand() {
+        std::env::set_var("PM_TEST_TEMPLATE", "from-env");
+        let ctx = TemplateContext::new("api", "java", "/opt/pm", "2024-01-02", Some("r1"));
+
+        assert_eq!(
+            ctx.expand("{PM_PATH}/process_log/{name}-{date}.log")
+                .unwrap(),
+            "/opt/pm/process_log/api-2024-01-02.log"
+        );
+        assert_eq!(
+            ctx.expand("{type} {run_id} {instance} ${PM_TEST_TEMPLATE}")
+                .unwrap(),
+            "java r1 0 from-env"
+        );
+        // 不是变量名的花括号和转义
+        assert_eq!(
+            ctx.expand("ps aux | awk '{print $2}' {} {{name}} $HOME")
+                .unwrap(),
+            "ps aux | awk '{print $2}' {} {name} $HOME"
+        );
+        assert!(ctx.expand("{unknown}").is_err());
+        assert!(ctx.expand("${PM_TEST_TEMPLATE_MISSING}").is_err());
+
+        // 预览时没有 run_id
+        let preview = TemplateContext::new("api", "java", "/opt/pm", "2024-01-02", None);
+        assert_eq!(preview.expand("{name}-{run_id}").unwrap(), "api-{run_id}");
+
+        // ${ENV} 使用进程的环境变量
+        let mut item = ProcessItem {
+            name: "api".to_string(),
+            command: "serve --port ${PORT} ${PM_TEST_TEMPLATE}".to_string(),
+            env: [("PORT".to_string(), "8080".to_string())].into(),
+            ..Default::default()
+        };
+        item.validate().unwrap();
+        assert_eq!(
+            item.expand(&ctx).unwrap().command,
+            "serve --port 8080 from-env"
+        );
+        item.clear_env = true;
+        assert!(item.validate().is_err());
+        // 环境变量文件无法读取时留给启动时报错
+        item.env_files = vec!["/nonexistent/pm-test.env".to_string()];
+        assert_eq!(
+            item.expand(&ctx).unwrap().command,
+            "serve --port 8080 ${PM_TEST_TEMPLATE}"
+        );
+    }
+
+    #[test]
+    fn test_spawn_env() {
+        let profile = temp_profile("env");
+        let work_dir = format!("{}/work", profile);
+        std::fs::create_dir_all(&work_dir).unwrap();
+        std::fs::write(
+            format!("{}/app.env", work_dir),
+            "# 注释\nexport FOO=from-file\nBAR=\"a b\\tc\"  # 注释\nBAZ='$FOO'\n",
+        )
+        .unwrap();
+
+        // env 覆盖 env_files 中的同名变量，相对路径相对于 cwd
+        let log_path = format!("{}/env.log", profile);
+        let item = ProcessItem {
+            name: "env".to_string(),
+            command: r#"pwd; echo "$FOO|$BAR|$BAZ|${HOME:-none}""#.to_string(),
+            log_path: Some(log_path.clone()),
+            cwd: Some("{PM_PATH}/work".to_string()),
+            env: [("FOO".to_string(), "{name}".to_string())].into(),
+            env_files: vec!["app.env".to_string()],
+            clear_env: true,
+            ..Default::default()
+        };
+        let ctx = TemplateContext::new("env", "", &profile, "", None);
+        let options = item.expand(&ctx).unwrap().spawn_options().unwrap();
+        process::spawn_detached(&item.command, &options)
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&log_path).unwrap(),
+            format!("{}\nenv|a b\tc|$FOO|none\n", work_dir)
+        );
+
+        // 文件格式错误时报告行号
+        std::fs::write(format!("{}/app.env", work_dir), "FOO=1\nBAR\n").unwrap();
+        let options = item.expand(&ctx).unwrap().spawn_options();
+        assert!(matches!(
+            options,
+            Err(Error::ConfigParse { line: Some(2), .. })
+        ));
+    }
+
+    #[test]
+    fn test_run_as_user() {
+        let profile = temp_profile("user");
+        let log_path = format!("{}/user.log", profile);
+        let item = ProcessItem {
+            name: "user".to_string(),
+            command: "id -u; id -g; id -G; umask".to_string(),
+            log_path: Some(log_path.clone()),
+            user: "nobody".to_string(),
+            supplementary_groups: vec!["65534".to_string()],
+            umask: "027".to_string(),
+            ..Default::default()
+        };
+        item.validate().unwrap();
+        let options = item.spawn_options().unwrap();
+        let credentials = options.credentials.clone().unwrap();
+        assert_eq!(credentials.uid.as_raw(), 65534);
+
+        let result = process::spawn_detached(&item.command, &options);
+        if nix::unistd::Uid::effective().is_root() {
+            result.unwrap().wait().unwrap();
+            assert_eq!(
+                std::fs::read_to_string(&log_path).unwrap(),
+                format!("65534\n{}\n65534\n0027\n", credentials.gid)
+            );
+        } else {
+            assert!(matches!(result, Err(Error::Permission(_))));
+        }
+
+        // 添加时检查用户、组和 umask
+        for (user, group, umask) in [
+            ("pm-test-missing-user", "", ""),
+            ("", "pm-test-missing-group", ""),
+            ("", "", "999"),
+        ] {
+            let item = ProcessItem {
+                user: user.to_string(),
+                group: group.to_string(),
+                umask: umask.to_string(),
+                ..item.clone()
+            };
+            assert!(matches!(item.validate(), Err(Error::Invalid(_))));
+        }
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        let item = ProcessItem {
+            name: "limits".to_string(),
+            command: "sleep 30".to_string(),
+            limits: ResourceLimits {
+                nofile: "256".to_string(),
+                address_space: "4G".to_string(),
+                core: "0".to_string(),
+                nice: Some(5),
+                oom_score_adj: Some(500),
+                cpu_affinity: "0".to_string(),
+            },
+            ..Default::default()
+        };
+        item.validate().unwrap();
+        let mut child =
+            process::spawn_detached(&item.command, &item.spawn_options().unwrap()).unwrap();
+        let pid = child.id();
+
+        let limits = limits::read_process_limits(pid).unwrap();
+        assert_eq!(limits.nofile, "256");
+        assert_eq!(limits.address_space, (4u64 << 30).to_string());
+        assert_eq!(limits.core, "0");
+        let oom = std::fs::read_to_string(format!("/proc/{}/oom_score_adj", pid)).unwrap();
+        assert_eq!(oom.trim(), "500");
+        // /proc/<pid>/stat 的第 19 个字段是 nice 值
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap();
+        let mut fields = stat.rsplit(')').next().unwrap().split_whitespace();
+        assert_eq!(fields.nth(16), Some("5"));
+        let cpu_set = nix::sched::sched_getaffinity(nix::unistd::Pid::from_raw(pid as i32));
+        assert!(cpu_set.unwrap().is_set(0).unwrap());
+        assert!(process::get_process_stats(&[pid])[0].limits.is_some());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        for limits in [
+            ResourceLimits {
+                nofile: "many".to_string(),
+                ..Default::default()
+            },
+            ResourceLimits {
+                nice: Some(20),
+                ..Default::default()
+            },
+            ResourceLimits {
+                cpu_affinity: "3-1".to_string(),
+                ..Default::default()
+            },
+        ] {
+            let item = ProcessItem {
+                limits,
+                ..item.clone()
+            };
+            assert!(matches!(item.validate(), Err(Error::Invalid(_))));
+        }
+    }
+
+    #[test]
+    fn test_cgroup_tree() {
+        let profile = temp_profile("cgroup");
+        let mut state = StateStore::load(&profile).unwrap();
+        let item = ProcessItem {
+            name: "tree".to_string(),
+            command: "sleep 30 & sleep 31".to_string(),
+            cgroup: CgroupConfig {
+                enabled: true,
+                pids_max: "64".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let pid = match start_one(&mut state, &profile, &item) {
+            StartStatus::Started(pid) => pid,
+            other => panic!("启动失败: {}", other),
+        };
+
+        let record = state.get("tree").unwrap().clone();
+        match record.cgroup {
+            Some(path) => {
+                // bash 的子进程也在 cgroup 中，停止时一起结束
+                let mut pids = vec![];
+                for _ in 0..50 {
+                    pids = ProcessManager::tree_pids(&state, &item).unwrap();
+                    if pids.len() >= 3 {
+                        break;
+                    }
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+                assert!(pids.len() >= 3 && pids.contains(&pid), "{:?}", pids);
+                assert_eq!(stop_one(&mut state, &item), StopStatus::Stopped);
+                assert!(pids.iter().all(|it| !process::is_alive(*it)));
+                assert!(!std::path::Path::new(&path).exists());
+            }
+            // cgroup 不可用时退回到只跟踪主进程
+            None => {
+                assert!(record.cgroup_warning.is_some());
+                assert_ne!(stop_one(&mut state, &item), StopStatus::AlreadyStopped);
+            }
+        }
+
+        let invalid = ProcessItem {
+            cgroup: CgroupConfig {
+                cpu_max: "fast".to_string(),
+                ..Default::default()
+            },
+            ..item.clone()
+        };
+        assert!(matches!(invalid.validate(), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn test_stop_process_tree() {
+        let profile = temp_profile("tree");
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        // 子 shell 中后台运行的进程会脱离父进程，但仍在启动时创建的会话中
+        pm.add(ProcessItem {
+            name: "tree".to_string(),
+            command: "(sleep 33 &); sleep 31 & sleep 30".to_string(),
+            stop_timeout: 1,
+            ..Default::default()
+        })
+        .unwrap();
+        let names = vec!["tree".to_string()];
+        let pid = match &pm.start(names.clone())[0].1 {
+            StartStatus::Started(pid) => *pid,
+            other => panic!("启动失败: {}", other),
+        };
+        let mut pids = vec![];
+        for _ in 0..50 {
+            pids = pm.status(names.clone()).remove(0).pids;
+            if pids.len() >= 4 {
+                break;
+            }
+        }
+        assert_eq!(pids.len(), 4, "{:?}", pids);
+
+        // 主进程退出后仍能找到留下的进程
+        let sigkill = nix::sys::signal::Signal::SIGKILL;
+        nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid as i32), sigkill).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        assert!(pm.run_record("tree").is_some());
+        let (_, preview) = pm.stop_preview(names.clone()).remove(0);
+        let commands = preview
+            .unwrap()
+            .into_iter()
+            .map(|it| it.command)
+            .collect::<Vec<_>>();
+        assert_eq!(commands.len(), 3, "{:?}", commands);
+        assert!(commands.contains(&"sleep 33".to_string()));
+
+        assert_eq!(pm.stop(names)[0].1, StopStatus::Stopped);
+        assert!(pids.iter().all(|it| !process::is_alive(*it)));
+
+        // 通过pid查询命令找到的进程不是 pm 启动的，不结束所在会话中的其他进程
+        let pid_file = format!("{}/foreign.pid", profile);
+        let mut foreign = std::process::Command::new("setsid")
+            .arg("bash")
+            .arg("-c")
+            .arg(format!(
+                "(sleep 34 &); echo $$ > {}; exec sleep 35",
+                pid_file
+            ))
+            .spawn()
+            .unwrap();
+        let mut session = vec![];
+        for _ in 0..50 {
+            std::thread::sleep(Duration::from_millis(100));
+            session = process::process_tree(&[foreign.id()], true);
+            if session.len() == 2 && std::path::Path::new(&pid_file).exists() {
+                break;
+            }
+        }
+        let sibling = session
+            .iter()
+            .find(|it| it.command == "sleep 34")
+            .expect("会话中没有其他进程")
+            .pid;
+        pm.add(ProcessItem {
+            name: "foreign".to_string(),
+            command: "sleep 35".to_string(),
+            pid_search_cmd: format!("cat {}", pid_file),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            pm.stop(vec!["foreign".to_string()])[0].1,
+            StopStatus::Stopped
+        );
+        foreign.wait().unwrap();
+        assert!(process::is_alive(sibling));
+        nix::sys::signal::kill(nix::unistd::Pid::from_raw(sibling as i32), sigkill).unwrap();
+    }
+
+    #[test]
+    fn test_health_checks() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let check = |probe: Probe| HealthCheck {
+            timeout: 1,
+            ..HealthCheck::new(probe)
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let tcp = check(format!("tcp:{}", port).parse().unwrap());
+        assert_eq!(tcp.probe(""), Ok(()));
+        let server = std::thread::spawn(move || {
+            // 第一个连接来自 TCP 检查
+            let mut stream = listener.incoming().nth(1).unwrap().unwrap();
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).unwrap();
+            assert!(buf[..n].starts_with(b"GET /ready HTTP/1.0"));
+            stream
+                .write_all(b"HTTP/1.0 503 Service Unavailable\r\n\r\n")
+                .unwrap();
+        });
+        let http = check(Probe::Http {
+            url: format!("http://127.0.0.1:{}/ready", port),
+            status: Some(503),
+        });
+        assert_eq!(http.probe(""), Ok(()));
+        server.join().unwrap();
+        assert!(tcp.probe("").is_err());
+
+        assert_eq!(check("exec:exit 0".parse().unwrap()).probe(""), Ok(()));
+        assert!(check("exec:exit 1".parse().unwrap()).probe("").is_err());
+
+        let profile = temp_profile("health");
+        // 超时后连同检查命令启动的子进程一起结束
+        let child_pid = format!("{}/child.pid", profile);
+        let exec = check(
+            format!("exec:sleep 5 & echo $! > {}; wait", child_pid)
+                .parse()
+                .unwrap(),
+        );
+        assert!(exec.probe("").is_err());
+        let pid = std::fs::read_to_string(&child_pid).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(!process::is_alive(pid.trim().parse().unwrap()));
+        let pid_file = format!("{}/app.pid", profile);
+        std::fs::write(&pid_file, std::process::id().to_string()).unwrap();
+        assert_eq!(
+            check(format!("pid-file:{}", pid_file).parse().unwrap()).probe(""),
+            Ok(())
+        );
+        let log_path = format!("{}/app.log", profile);
+        std::fs::write(&log_path, "starting\nlistening on :8080\n").unwrap();
+        let log_regex = check("log-regex:listening on :[0-9]+$".parse().unwrap());
+        assert_eq!(log_regex.probe(&log_path), Ok(()));
+        assert!(check("log-regex:ready".parse().unwrap())
+            .probe(&log_path)
+            .is_err());
+
+        assert!(check("log-regex:(".parse().unwrap()).validate().is_err());
+        assert!(check("http:https://localhost".parse().unwrap())
+            .validate()
+            .is_err());
+        assert!("grpc:8080".parse::<Probe>().is_err());
+
+        // IPv6 地址需要方括号，保存的主机不带方括号
+        let ipv6 = Probe::Tcp {
+            host: "::1".to_string(),
+            port: 8080,
+        };
+        assert_eq!("tcp:[::1]:8080".parse::<Probe>(), Ok(ipv6.clone()));
+        assert_eq!(ipv6.to_string(), "tcp:[::1]:8080");
+        assert!("tcp:::1:8080".parse::<Probe>().is_err());
+        if let Ok(listener) = TcpListener::bind("[::1]:0") {
+            let port = listener.local_addr().unwrap().port();
+            let probe = format!("tcp:[::1]:{}", port).parse().unwrap();
+            assert_eq!(check(probe).probe(""), Ok(()));
+        }
+
+        // 配置保存后再读取不变，status 对运行中的进程执行检查
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        let health = HealthCheck {
+            interval: 1,
+            retries: 1,
+            ..check("exec:test -f {PM_PATH}/healthy".parse().unwrap())
+        };
+        pm.add(ProcessItem {
+            name: "checked".to_string(),
+            command: "sleep 41".to_string(),
+            restart_policy: Some(RestartPolicy::Always),
+            restart_backoff: 0,
+            health: Some(health.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        assert_eq!(pm.get("checked").unwrap().health, Some(health));
+        let names = vec!["checked".to_string()];
+        let pid = match &pm.start(names.clone())[0].1 {
+            StartStatus::Started(pid) => *pid,
+            other => panic!("启动失败: {}", other),
+        };
+        assert!(matches!(
+            pm.status(names.clone())[0].health,
+            Some(HealthStatus::Unhealthy(_))
+        ));
+        std::fs::write(format!("{}/healthy", profile), "").unwrap();
+        assert_eq!(
+            pm.status(names.clone())[0].health,
+            Some(HealthStatus::Healthy)
+        );
+
+        // 守护进程结束不健康的进程并重启
+        std::fs::remove_file(format!("{}/healthy", profile)).unwrap();
+        let mut supervisor = Supervisor::new(&profile);
+        for _ in 0..50 {
+            supervisor.tick().unwrap();
+            let state = StateStore::load(&profile).unwrap();
+            if state.get("checked").is_some_and(|it| it.pid != pid) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        assert!(!process::is_alive(pid));
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        assert!(pm.run_record("checked").is_some_and(|it| it.pid != pid));
+        assert!(pm.state.last_exit("checked").unwrap().unhealthy);
+        pm.stop(names);
+    }
+
+    #[test]
+    fn test_start_wait() {
+        let profile = temp_profile("wait");
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        let ready_file = format!("{}/ready", profile);
+        pm.add(ProcessItem {
+            name: "slow".to_string(),
+            command: format!("sleep 1; touch {}; sleep 42", ready_file),
+            health: Some(HealthCheck::new(
+                format!("exec:test -f {}", ready_file).parse().unwrap(),
+            )),
+            ..Default::default()
+        })
+        .unwrap();
+        pm.add(ProcessItem {
+            name: "detected".to_string(),
+            command: "touch {PM_PATH}/{name}; sleep 42".to_string(),
+            detection_start_cmd: "find {PM_PATH} -name {name}".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        pm.add(ProcessItem {
+            name: "never".to_string(),
+            command: "sleep 42".to_string(),
+            detection_start_cmd: "true".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        pm.add(ProcessItem {
+            name: "exited".to_string(),
+            command: "exit 1".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let names = ["slow", "detected", "never", "exited"]
+            .map(String::from)
+            .to_vec();
+        pm.start(names.clone());
+        std::thread::sleep(Duration::from_millis(600));
+
+        // 就绪时长从启动时开始计算，不是从开始等待时
+        let results = pm.wait_ready(names.clone(), Duration::from_secs(3));
+        match &results[0] {
+            (name, ReadyStatus::Ready(elapsed)) if name == "slow" => {
+                assert!(*elapsed >= Duration::from_secs(1), "{:?}", elapsed)
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(results[1].1, ReadyStatus::Ready(_)));
+        assert!(matches!(results[2].1, ReadyStatus::Timeout(_)));
+        assert_eq!(results[3].1, ReadyStatus::NotRunning);
+        pm.stop(names);
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let profile = temp_profile("deps");
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        for (name, tags, depends_on) in [
+            ("gateway", vec![], vec!["api"]),
+            ("registry", vec![], vec![]),
+            ("config", vec!["core"], vec!["registry"]),
+            ("api", vec!["core"], vec!["tag:core"]),
+        ] {
+            let result = pm.add(ProcessItem {
+                name: name.to_string(),
+                tags: tags.into_iter().map(String::from).collect(),
+                command: "sleep 43".to_string(),
+                depends_on: depends_on.into_iter().map(String::from).collect(),
+                ..Default::default()
+            });
+            // 依赖的进程需要先添加
+            if name == "gateway" {
+                assert!(matches!(result, Err(Error::Invalid(_))));
+            } else {
+                result.unwrap();
+            }
+        }
+        pm.add(ProcessItem {
+            name: "gateway".to_string(),
+            command: "sleep 43".to_string(),
+            depends_on: vec!["api".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut registry = pm.get("registry").unwrap().clone();
+        registry.depends_on = vec!["gateway".to_string()];
+        match pm.update("registry", registry) {
+            Err(Error::Invalid(msg)) => assert!(msg.contains("循环"), "{}", msg),
+            other => panic!("{:?}", other.err()),
+        }
+        assert!(pm.get("registry").unwrap().depends_on.is_empty());
+
+        let results = pm.start(vec!["gateway".to_string()]);
+        let order = results
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(order, ["registry", "config", "api", "gateway"]);
+        assert!(results
+            .iter()
+            .all(|(_, status)| matches!(status, StartStatus::Started(_))));
+
+        // 改名时依赖跟随新名称
+        let mut api = pm.get("api").unwrap().clone();
+        api.name = "api-v2".to_string();
+        pm.update("api", api).unwrap();
+        assert_eq!(pm.get("gateway").unwrap().depends_on, ["api-v2"]);
+
+        let names = ["registry", "config", "api-v2", "gateway"].map(String::from);
+        let order = pm.stop(names.to_vec());
+        let order = order
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(order, ["gateway", "api-v2", "config", "registry"]);
+
+        // 配置文件中的循环依赖在加载时报错
+        std::fs::write(
+            format!("{}/.config.json", profile),
+            r#"{version: 1, processes: [
+                {name: "a", command: "true", depends_on: ["b"]},
+                {name: "b", command: "true", depends_on: ["a"]},
+            ]}"#,
+        )
+        .unwrap();
+        match ProcessManager::new(&profile) {
+            Err(Error::Invalid(msg)) => assert!(msg.contains("a -> b -> a"), "{}", msg),
+            other => panic!("{:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_parallel_start() {
+        let profile = temp_profile("parallel");
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        // 检测启动命令较慢，依赖的进程创建标记文件后才算就绪
+        for name in ["db1", "db2", "db3"] {
+            pm.add(ProcessItem {
+                name: name.to_string(),
+                command: format!("sh -c 'touch {{PM_PATH}}/{}.up; exec sleep 45'", name),
+                detection_start_cmd: "sleep 1.5".to_string(),
+                health: Some(HealthCheck::new(Probe::Exec {
+                    command: format!("test -f {{PM_PATH}}/{}.up", name),
+                })),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        pm.add(ProcessItem {
+            name: "app".to_string(),
+            command: "sh -c 'ls {PM_PATH} > {PM_PATH}/app.seen; exec sleep 46'".to_string(),
+            detection_start_cmd: "sleep 1.5".to_string(),
+            health: Some(HealthCheck::new(Probe::Exec {
+                command: "test -s {PM_PATH}/app.seen".to_string(),
+            })),
+            depends_on: ["db1", "db2", "db3"].map(String::from).to_vec(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        pm.set_parallel(4);
+        let begin = std::time::Instant::now();
+        let results = pm.start(vec!["app".to_string()]);
+        // 依次启动至少需要 6 秒
+        assert!(
+            begin.elapsed() < Duration::from_secs(5),
+            "{:?}",
+            begin.elapsed()
+        );
+        let order = results
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(order, ["db1", "db2", "db3", "app"]);
+        assert!(results
+            .iter()
+            .all(|(_, status)| matches!(status, StartStatus::Started(_))));
+        let ready = pm.wait_ready(vec!["app".to_string()], Duration::from_secs(5));
+        assert!(matches!(ready[0].1, ReadyStatus::Ready(_)), "{:?}", ready);
+        let seen = std::fs::read_to_string(format!("{}/app.seen", profile)).unwrap();
+        for name in ["db1", "db2", "db3"] {
+            assert!(seen.contains(&format!("{}.up", name)), "{}", seen);
+        }
+
+        let names = ["db1", "db2", "db3", "app"].map(String::from).to_vec();
+        let statuses = pm.status(names.clone());
+        assert_eq!(statuses.len(), 4);
+        assert!(statuses.iter().all(|it| it.is_running()));
+
+        let results = pm.stop(names);
+        assert_eq!(results[0].0, "app");
+        assert!(results
+            .iter()
+            .all(|(_, status)| matches!(status, StopStatus::Stopped)));
+        assert!(pm.status(vec!["db1".to_string()])[0].pids.is_empty());
+    }
+
+    #[test]
+    fn test_process_groups() {
+        let profile = temp_profile("group");
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        pm.add(ProcessItem {
+            name: "web".to_string(),
+            command: "echo $FOO $BAR; pwd; sleep 44".to_string(),
+            env: [("FOO".to_string(), "member".to_string())].into(),
+            ..Default::default()
+        })
+        .unwrap();
+        pm.add(ProcessItem {
+            name: "worker".to_string(),
+            command: "sleep 44".to_string(),
+            restart_policy: Some(RestartPolicy::Never),
+            ..Default::default()
+        })
+        .unwrap();
+        let group = ProcessGroup {
+            name: "backend".to_string(),
+            members: vec!["web".to_string(), "worker".to_string()],
+            cwd: "/tmp".to_string(),
+            env: [
+                ("FOO".to_string(), "group".to_string()),
+                ("BAR".to_string(), "group".to_string()),
+            ]
+            .into(),
+            log_dir: "{PM_PATH}/".to_string(),
+            restart_policy: Some(RestartPolicy::OnFailure),
+            ..Default::default()
+        };
+        pm.add_group(group.clone()).unwrap();
+        assert!(matches!(
+            pm.add_group(group.clone()),
+            Err(Error::Invalid(_))
+        ));
+        assert!(matches!(
+            pm.add_group(ProcessGroup {
+                name: "other".to_string(),
+                members: vec!["missing".to_string()],
+                ..Default::default()
+            }),
+            Err(Error::Invalid(_))
+        ));
+
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        assert_eq!(pm.get_group("backend"), Some(&group));
+        let web = pm.expand(pm.get("web").unwrap()).unwrap();
+        assert_eq!(web.cwd(), "/tmp");
+        assert_eq!(web.env["FOO"], "member");
+        assert_eq!(web.env["BAR"], "group");
+        assert_eq!(web.log_path(), format!("{}/web.log", pm.pm_path));
+        assert_eq!(web.restart_policy(), RestartPolicy::OnFailure);
+        // 配置中保存的仍是成员自己的设置
+        assert!(pm.get("web").unwrap().log_path.is_none());
+        // 成员自己配置的默认值不会被分组覆盖
+        let worker = pm.effective(pm.get("worker").unwrap());
+        assert_eq!(worker.restart_policy(), RestartPolicy::Never);
+        assert_eq!(worker.cwd(), "/tmp");
+
+        let members = pm.group_members("backend").unwrap();
+        assert_eq!(members, ["web", "worker"]);
+        assert!(pm
+            .start(members.clone())
+            .iter()
+            .all(|(_, status)| matches!(status, StartStatus::Started(_))));
+        std::thread::sleep(Duration::from_millis(200));
+        let log = std::fs::read_to_string(format!("{}/web.log", profile)).unwrap();
+        assert_eq!(log, "member group\n/tmp\n");
+        pm.stop(members);
+
+        // 改名和删除进程时成员跟随变化
+        let mut web = pm.get("web").unwrap().clone();
+        web.name = "web-v2".to_string();
+        pm.update("web", web).unwrap();
+        pm.remove(vec!["worker".to_string()]).unwrap();
+        assert_eq!(pm.group_members("backend").unwrap(), ["web-v2"]);
+        pm.remove_group("backend").unwrap();
+        assert!(pm.group_members("backend").is_err());
+    }
+
+    #[test]
+    fn test_update() {
+        let profile = temp_profile("update");
+        let mut pm = ProcessManager::new(&profile).unwrap();
+        for name in ["api", "web"] {
+            pm.add(ProcessItem {
+                name: name.to_string(),
+                command: "sleep 30".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        let mut item = pm.get("api").unwrap().clone();
+        item.name = "api-v2".to_string();
+        item.tags.push("backend".to_string());
+        pm.update("api", item.clone()).unwrap();
+
+        let pm = &mut ProcessManager::new(&profile).unwrap();
+        assert!(pm.get("api").is_none());
+        assert_eq!(pm.get("api-v2").unwrap().tags, vec!["backend"]);
+
+        // 重名、未找到、无效配置都不会写入
+        item.name = "web".to_string();
+        assert!(matches!(
+            pm.update("api-v2", item.clone()),
+            Err(Error::DuplicateName(_))
+        ));
+        item.name = "missing".to_string();
+        assert!(matches!(
+            pm.update("missing", item.clone()),
+            Err(Error::NotFound(_))
+        ));
+        item.name = "api-v2".to_string();
+        item.stop_signal = "SIGNOPE".to_string();
+        assert!(matches!(
+            pm.update("api-v2", item.clone()),
+            Err(Error::Invalid(_))
+        ));
+        item.stop_signal = "SIGTERM".to_string();
+        item.log_path = Some("{unknown}.log".to_string());
+        assert!(matches!(pm.update("api-v2", item), Err(Error::Invalid(_))));
+        assert_eq!(
+            ProcessManager::new(&profile)
+                .unwrap()
+                .get("api-v2")
+                .unwrap()
+                .stop_signal,
+            "SIGTERM"
+        );
+    }
+
+    #[test]
+    fn test_profiles() {
+        let home = temp_profile("profiles");
+        let profiles = Profiles::new(&home);
+        assert_eq!(profiles.current(), DEFAULT_PROFILE);
+        assert_eq!(profiles.list().unwrap(), vec![DEFAULT_PROFILE]);
+
+        let dir = profiles.switch("staging-box").unwrap();
+        assert!(std::path::Path::new(&dir).is_dir());
+        profiles.switch("dev").unwrap();
+        assert_eq!(profiles.current(), "dev");
+        assert_eq!(
+            profiles.list().unwrap(),
+            vec![DEFAULT_PROFILE, "dev", "staging-box"]
+        );
+        assert!(profiles.switch("../escape").is_err());
+
+        profiles.switch(DEFAULT_PROFILE).unwrap();
+        assert_eq!(
+            profiles.dir(&profiles.current()).unwrap().to_str(),
+            Some(home.as_str())
+        );
+
+        // --profile 指定的目录不存在时自动创建
+        let explicit = format!("{}/explicit/nested", home);
+        assert_eq!(Profiles::resolve(Some(&explicit)).unwrap(), explicit);
+        assert!(std::path::Path::new(&explicit).is_dir());
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let profile = temp_profile("errors");
+        std::fs::write(
+            format!("{}/.config.json", profile),
+            "{\n  processes: [\n    {name: \"a\",, }\n  ]\n}",
+        )
+        .unwrap();
+        match ProcessManager::new(&profile) {
+            Err(Error::ConfigParse { line, column, .. }) => {
+                assert_eq!(line, Some(3));
+                assert!(column.is_some());
+            }
+            _ => panic!("应返回配置解析错误"),
+        }
+
+        // 日志目录不存在时启动失败，不会 panic
+        let result = process::swpan("true", &format!("{}/missing/dir/app.log", profile));
+        assert!(matches!(result, Err(Error::Io { path, .. }) if path.ends_with("app.log")));
+    }
+
+    #[test]
+    fn test_concurrent_config_writes() {
+        let profile = temp_profile("concurrent");
+        ProcessManager::new(&profile).unwrap();
+
+        // 同时加载再各自添加，不会丢失任何一个
+        let handles = (0..8)
+            .map(|i| {
+                let profile = profile.clone();
+                std::thread::spawn(move || {
+                    let mut pm = ProcessManager::new(&profile).unwrap();
+                    pm.add(ProcessItem {
+                        name: format!("p{}", i),
+                        command: "sleep 30".to_string(),
+                        ..Default::default()
+                    })
+                    .unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let mut stale = ProcessManager::new(&profile).unwrap();
+        assert_eq!(stale.conf.processes.len(), 8);
+
+        // 被其他 pm 修改过的进程不会被旧的副本覆盖
+        let mut fresh = ProcessManager::new(&profile).unwrap();
+        let mut item = fresh.get("p0").unwrap().clone();
+        item.comment = "fresh".to_string();
+        fresh.update("p0", item.clone()).unwrap();
+        item.comment = "stale".to_string();
+        assert!(matches!(
+            stale.update("p0", item),
+            Err(Error::Conflict { .. })
+        ));
+        // 其他进程的修改不受影响，并且保留了上一版本的备份
+        stale.remove(vec!["p1".to_string()]).unwrap();
+        let pm = ProcessManager::new(&profile).unwrap();
+        assert_eq!(pm.get("p0").unwrap().comment, "fresh");
+        assert!(pm.get("p1").is_none());
+        let backup = std::fs::read_to_string(format!("{}/.config.json.bak", profile)).unwrap();
+        assert!(backup.contains("\"p1\""));
+    }
+
+    #[test]
+    fn test_concurrent_state_writes() {
+        let profile = temp_profile("concurrent-state");
+
+        // 同时加载再各自记录，不会丢失任何一个
+        let handles = (0..8)
+            .map(|i| {
+                let profile = profile.clone();
+                std::thread::spawn(move || {
+                    let mut state = StateStore::load(&profile).unwrap();
+                    let record = RunRecord::new(std::process::id(), "test");
+                    state.record(&format!("p{}", i), record).unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let mut stale = StateStore::load(&profile).unwrap();
+        assert!((0..8).all(|i| stale.get(&format!("p{}", i)).is_some()));
+
+        // 旧的副本在最新的状态上修改
+        let mut fresh = StateStore::load(&profile).unwrap();
+        fresh.record_stop("p0").unwrap();
+        stale.record_stop("p1").unwrap();
+        let state = StateStore::load(&profile).unwrap();
+        assert!(state.get("p0").is_none() && state.get("p1").is_none());
+        assert!(state.last_exit("p0").is_some_and(|it| it.stopped));
+        assert!(state.get("p2").is_some());
+    }
+
+    #[test]
+    fn test_config_migration() {
+        let profile = temp_profile("migration");
+        let conf_path = format!("{}/.config.json", profile);
+        // 版本 0：没有 version 字段，并且省略了可选字段
+        let old = r#"{processes: [{name: "api", command: "sleep 30"}]}"#;
+        std::fs::write(&conf_path, old).unwrap();
+
+        let pm = ProcessManager::new(&profile).unwrap();
+        assert_eq!(pm.conf.version, migrate::CONFIG_VERSION);
+        assert_eq!(pm.get("api").unwrap().stop_signal, "SIGTERM");
+        assert!(pm.get("api").unwrap().comment.is_empty());
+        // 原文件被备份，升级后的文件带有版本号
+        let backup = std::fs::read_to_string(format!("{}.v0.bak", conf_path)).unwrap();
+        assert_eq!(backup, old);
+        let upgraded: serde_json::Value =
+            json5::from_str(&std::fs::read_to_string(&conf_path).unwrap()).unwrap();
+        assert_eq!(
+            migrate::version_of(&upgraded).unwrap(),
+            migrate::CONFIG_VERSION
+        );
+
+        // 版本 1：默认值表示继承分组的配置，升级后省略
+        let v1 = r#"{version: 1, processes: [
+            {name: "api", command: "sleep 30", cwd: "", log_path: "api.log", restart_policy: "never"},
+        ]}"#;
+        std::fs::write(&conf_path, v1).unwrap();
+        let pm = ProcessManager::new(&profile).unwrap();
+        let api = pm.get("api").unwrap();
+        assert!(api.cwd.is_none() && api.restart_policy.is_none());
+        assert_eq!(api.log_path.as_deref(), Some("api.log"));
+
+        // 更高版本的配置不会被覆盖
+        let newer = format!(
+            "{{version: {}, processes: []}}",
+            migrate::CONFIG_VERSION + 1
+        );
+        std::fs::write(&conf_path, &newer).unwrap();
+        assert!(matches!(
+            ProcessManager::new(&profile),
+            Err(Error::Invalid(_))
+        ));
+        assert_eq!(std::fs::read_to_string(&conf_path).unwrap(), newer);
+    }
 }
@@ -0,0 +1,197 @@
+use std::io;
+
+use nix::{
+    sched::CpuSet,
+    sys::resource::{Resource, RLIM_INFINITY},
+    unistd::Pid,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    process,
+};
+
+/// 进程的资源限制，字段为空时继承 pm 的设置
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// 最大打开文件数（RLIMIT_NOFILE），数字或 `unlimited`
+    #[serde(default)]
+    pub nofile: String,
+    /// 最大虚拟内存（RLIMIT_AS），字节数，可以带 K/M/G 后缀，或 `unlimited`
+    #[serde(default)]
+    pub address_space: String,
+    /// 最大 core 文件大小（RLIMIT_CORE），字节数，可以带 K/M/G 后缀，或 `unlimited`
+    #[serde(default)]
+    pub core: String,
+    /// nice 值，-20 到 19
+    #[serde(default)]
+    pub nice: Option<i32>,
+    /// OOM 分数调整，-1000 到 1000
+    #[serde(default)]
+    pub oom_score_adj: Option<i32>,
+    /// 允许运行的 CPU 列表，如 `0-3,6`
+    #[serde(default)]
+    pub cpu_affinity: String,
+}
+
+/// 解析后的资源限制，在子进程 exec 之前应用
+#[derive(Clone, Debug)]
+pub struct PreparedLimits {
+    rlimits: Vec<(Resource, u64)>,
+    nice: Option<i32>,
+    /// 写入 `/proc/self/oom_score_adj` 的内容
+    oom_score_adj: Option<Vec<u8>>,
+    cpu_set: Option<CpuSet>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        self == &ResourceLimits::default()
+    }
+
+    /// 检查并解析资源限制，没有配置时返回 None
+    pub fn prepare(&self) -> Result<Option<PreparedLimits>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+
+        let mut rlimits = vec![];
+        for (name, resource, value) in [
+            ("nofile", Resource::RLIMIT_NOFILE, &self.nofile),
+            ("address_space", Resource::RLIMIT_AS, &self.address_space),
+            ("core", Resource::RLIMIT_CORE, &self.core),
+        ] {
+            if let Some(limit) = parse_limit(value)
+                .map_err(|_| Error::Invalid(format!("无效的资源限制 {}：{}", name, value)))?
+            {
+                rlimits.push((resource, limit));
+            }
+        }
+        if let Some(nice) = self.nice.filter(|it| !(-20..=19).contains(it)) {
+            return Err(Error::Invalid(format!(
+                "nice 应在 -20 到 19 之间：{}",
+                nice
+            )));
+        }
+        if let Some(adj) = self.oom_score_adj.filter(|it| !(-1000..=1000).contains(it)) {
+            return Err(Error::Invalid(format!(
+                "oom_score_adj 应在 -1000 到 1000 之间：{}",
+                adj
+            )));
+        }
+        let cpu_set =
+            if self.cpu_affinity.is_empty() {
+                None
+            } else {
+                Some(parse_cpu_list(&self.cpu_affinity).ok_or_else(|| {
+                    Error::Invalid(format!("无效的 CPU 列表：{}", self.cpu_affinity))
+                })?)
+            };
+
+        Ok(Some(PreparedLimits {
+            rlimits,
+            nice: self.nice,
+            oom_score_adj: self.oom_score_adj.map(|it| it.to_string().into_bytes()),
+            cpu_set,
+        }))
+    }
+}
+
+impl PreparedLimits {
+    /// 在子进程中应用，只调用异步信号安全的系统调用
+    ///
+    /// 软限制和硬限制设为相同的值；提高硬限制、降低 nice 值和 oom_score_adj 需要 root 权限
+    pub fn apply(&self) -> io::Result<()> {
+        for (resource, limit) in &self.rlimits {
+            nix::sys::resource::setrlimit(*resource, *limit, *limit)?;
+        }
+        if let Some(nice) = self.nice {
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        if let Some(adj) = &self.oom_score_adj {
+            process::write_in_child(c"/proc/self/oom_score_adj", adj)?;
+        }
+        if let Some(cpu_set) = &self.cpu_set {
+            nix::sched::sched_setaffinity(Pid::from_raw(0), cpu_set)?;
+        }
+        Ok(())
+    }
+}
+
+/// 解析资源限制的值，为空时返回 None
+fn parse_limit(value: &str) -> std::result::Result<Option<u64>, ()> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    if value == "unlimited" || value == "infinity" {
+        return Ok(Some(RLIM_INFINITY));
+    }
+    parse_size(value).map(Some).ok_or(())
+}
+
+/// 解析字节数，可以带 K/M/G/T 后缀（1024 进制）
+pub(crate) fn parse_size(value: &str) -> Option<u64> {
+    let (number, unit) = match value.char_indices().find(|(_, it)| !it.is_ascii_digit()) {
+        Some((i, _)) => value.split_at(i),
+        None => (value, ""),
+    };
+    let unit: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+/// 解析 `0-3,6` 格式的 CPU 列表
+fn parse_cpu_list(list: &str) -> Option<CpuSet> {
+    let mut cpu_set = CpuSet::new();
+    for part in list.split(',') {
+        let (start, end) = match part.trim().split_once('-') {
+            Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+            None => {
+                let cpu = part.trim().parse().ok()?;
+                (cpu, cpu)
+            }
+        };
+        if start > end {
+            return None;
+        }
+        for cpu in start..=end {
+            cpu_set.set(cpu).ok()?;
+        }
+    }
+    Some(cpu_set)
+}
+
+/// 从 `/proc/<pid>/limits` 读取的软限制
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProcessLimits {
+    pub nofile: String,
+    pub address_space: String,
+    pub core: String,
+}
+
+/// 读取进程实际生效的资源限制
+pub fn read_process_limits(pid: u32) -> Option<ProcessLimits> {
+    let data = std::fs::read_to_string(format!("/proc/{}/limits", pid)).ok()?;
+    let soft_limit = |name: &str| {
+        data.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|rest| rest.split_whitespace().next())
+            .unwrap_or_default()
+            .to_string()
+    };
+    Some(ProcessLimits {
+        nofile: soft_limit("Max open files"),
+        address_space: soft_limit("Max address space"),
+        core: soft_limit("Max core file size"),
+    })
+}
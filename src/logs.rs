@@ -0,0 +1,311 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufWriter, Read, Seek, SeekFrom, Write},
+    os::unix::fs::MetadataExt,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// 日志写入进程的子命令名，使用本库的可执行程序需要处理该子命令（见 [`run_log_writer`]）
+pub const LOG_WRITER_SUBCOMMAND: &str = "log-writer";
+
+/// 日志轮转配置，`max_size` 和 `max_age` 都为 0 时不轮转
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LogRotation {
+    /// 单个日志文件的最大字节数，0 表示不限制
+    #[serde(default)]
+    pub max_size: u64,
+    /// 单个日志文件的最长写入时长（秒），0 表示不限制
+    #[serde(default)]
+    pub max_age: u64,
+    /// 保留的历史日志个数
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+    /// 是否用 gzip 压缩历史日志
+    #[serde(default)]
+    pub compress: bool,
+}
+
+fn default_keep() -> usize {
+    5
+}
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        LogRotation {
+            max_size: 0,
+            max_age: 0,
+            keep: default_keep(),
+            compress: false,
+        }
+    }
+}
+
+impl LogRotation {
+    pub fn is_enabled(&self) -> bool {
+        self.max_size > 0 || self.max_age > 0
+    }
+}
+
+/// 日志写入进程的参数，序列化后作为子命令参数传递
+#[derive(Serialize, Deserialize)]
+pub struct LogWriterArgs {
+    pub log_path: String,
+    pub append: bool,
+    pub rotation: LogRotation,
+}
+
+/// 日志写入进程的入口：从标准输入按行读取进程输出并写入轮转日志，标准输入关闭后退出
+pub fn run_log_writer(args: &str) -> Result<()> {
+    let args: LogWriterArgs = json5::from_str(args)
+        .map_err(|e| Error::Invalid(format!("日志写入进程参数有误: {}", e)))?;
+    let mut writer = RotatingWriter::open(&args.log_path, args.rotation, args.append)?;
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut line = vec![];
+    loop {
+        line.clear();
+        if input.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        writer.write_line(&line)?;
+    }
+    writer.flush()
+}
+
+/// 按大小和时长轮转的日志文件
+///
+/// 轮转时 `app.log` 改名为 `app.log.1`（压缩时为 `app.log.1.gz`），原有的历史日志序号依次加一，
+/// 超出保留个数的被删除
+pub struct RotatingWriter {
+    path: String,
+    rotation: LogRotation,
+    file: BufWriter<File>,
+    size: u64,
+    opened_at: Instant,
+}
+
+impl RotatingWriter {
+    pub fn open(path: &str, rotation: LogRotation, append: bool) -> Result<Self> {
+        let file = File::options()
+            .create(true)
+            .append(append)
+            .write(true)
+            .truncate(!append)
+            .open(path)
+            .map_err(|e| Error::io(path, e))?;
+        let size = file.metadata().map_err(|e| Error::io(path, e))?.len();
+        Ok(RotatingWriter {
+            path: path.to_string(),
+            rotation,
+            file: BufWriter::new(file),
+            size,
+            opened_at: Instant::now(),
+        })
+    }
+
+    /// 写入一行，同一行不会被拆分到两个文件
+    pub fn write_line(&mut self, line: &[u8]) -> Result<()> {
+        if self.should_rotate(line.len() as u64) {
+            self.rotate().map_err(|e| Error::io(&self.path, e))?;
+        }
+        self.file
+            .write_all(line)
+            // 按行刷新，方便 pm logs 实时查看
+            .and_then(|_| self.file.flush())
+            .map_err(|e| Error::io(&self.path, e))?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.file.flush().map_err(|e| Error::io(&self.path, e))
+    }
+
+    fn should_rotate(&self, incoming: u64) -> bool {
+        let too_large = self.rotation.max_size > 0
+            && self.size > 0
+            && self.size + incoming > self.rotation.max_size;
+        let too_old = self.rotation.max_age > 0
+            && self.opened_at.elapsed() >= Duration::from_secs(self.rotation.max_age);
+        too_large || too_old
+    }
+
+    fn rotated_path(&self, index: usize) -> String {
+        if self.rotation.compress {
+            format!("{}.{}.gz", self.path, index)
+        } else {
+            format!("{}.{}", self.path, index)
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.rotation.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.rotation.keep));
+            for index in (1..self.rotation.keep).rev() {
+                let from = self.rotated_path(index);
+                if fs::metadata(&from).is_ok() {
+                    fs::rename(&from, self.rotated_path(index + 1))?;
+                }
+            }
+
+            if self.rotation.compress {
+                let mut source = File::open(&self.path)?;
+                let mut encoder =
+                    GzEncoder::new(File::create(self.rotated_path(1))?, Compression::default());
+                io::copy(&mut source, &mut encoder)?;
+                encoder.finish()?;
+                fs::remove_file(&self.path)?;
+            } else {
+                fs::rename(&self.path, self.rotated_path(1))?;
+            }
+        }
+
+        self.file = BufWriter::new(File::create(&self.path)?);
+        self.size = 0;
+        self.opened_at = Instant::now();
+        Ok(())
+    }
+}
+
+/// 读取文件最后 `n` 行
+pub fn tail_lines(path: &str, n: usize) -> Result<Vec<String>> {
+    read_tail(path, n).map_err(|e| Error::io(path, e))
+}
+
+fn read_tail(path: &str, n: usize) -> io::Result<Vec<String>> {
+    const CHUNK: u64 = 8192;
+
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if n == 0 || len == 0 {
+        return Ok(vec![]);
+    }
+
+    // 从文件末尾向前按块读取，直到包含足够的换行
+    let mut start = len;
+    let mut buf = vec![];
+    loop {
+        let read_from = start.saturating_sub(CHUNK);
+        let mut chunk = vec![0; (start - read_from) as usize];
+        file.seek(SeekFrom::Start(read_from))?;
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&buf);
+        buf = chunk;
+        start = read_from;
+
+        // 最后一个字符是换行时不算作一行
+        let newlines = buf[..buf.len() - 1].iter().filter(|&&b| b == b'\n').count();
+        if newlines >= n || start == 0 {
+            break;
+        }
+    }
+
+    let text = String::from_utf8_lossy(&buf);
+    let lines = text.lines().collect::<Vec<_>>();
+    let skip = lines.len().saturating_sub(n);
+    Ok(lines[skip..].iter().map(|it| it.to_string()).collect())
+}
+
+/// 持续读取日志新增的内容，支持文件被截断和轮转
+///
+/// 文件被截断时从头开始读取；文件被替换（inode 变化）时先读完旧文件剩余内容，再从头读取新文件
+pub struct LogFollower {
+    pub name: String,
+    path: PathBuf,
+    file: Option<File>,
+    ino: u64,
+    pos: u64,
+    /// 尚未读到换行的内容
+    partial: Vec<u8>,
+}
+
+impl LogFollower {
+    /// 从文件当前末尾开始跟踪，文件不存在时等待它被创建
+    pub fn new(name: &str, path: &str) -> Self {
+        let mut follower = LogFollower {
+            name: name.to_string(),
+            path: PathBuf::from(path),
+            file: None,
+            ino: 0,
+            pos: 0,
+            partial: vec![],
+        };
+        if let Ok(file) = File::open(path) {
+            if let Ok(meta) = file.metadata() {
+                follower.ino = meta.ino();
+                follower.pos = meta.len();
+                follower.file = Some(file);
+            }
+        }
+        follower
+    }
+
+    /// 读取新增的完整行
+    pub fn poll(&mut self) -> Result<Vec<String>> {
+        let mut lines = vec![];
+        self.poll_into(&mut lines)
+            .map_err(|e| Error::io(self.path.to_string_lossy(), e))?;
+        Ok(lines)
+    }
+
+    fn poll_into(&mut self, lines: &mut Vec<String>) -> io::Result<()> {
+        // 先读完当前打开的文件，文件被轮转时旧文件的剩余内容也不会丢失
+        self.read_available(lines)?;
+
+        match fs::metadata(&self.path) {
+            Ok(meta) if self.file.is_none() || meta.ino() != self.ino => {
+                // 文件被创建或被轮转
+                self.file = Some(File::open(&self.path)?);
+                self.ino = meta.ino();
+                self.pos = 0;
+                self.flush_partial(lines);
+                self.read_available(lines)?;
+            }
+            Ok(meta) if meta.len() < self.pos => {
+                // 文件被截断
+                self.pos = 0;
+                self.partial.clear();
+                self.read_available(lines)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn read_available(&mut self, lines: &mut Vec<String>) -> io::Result<()> {
+        let Some(ref mut file) = self.file else {
+            return Ok(());
+        };
+
+        let mut buf = vec![];
+        file.seek(SeekFrom::Start(self.pos))?;
+        let read = file.read_to_end(&mut buf)?;
+        self.pos += read as u64;
+
+        self.partial.extend_from_slice(&buf);
+        while let Some(idx) = self.partial.iter().position(|&b| b == b'\n') {
+            let line = self.partial.drain(..=idx).collect::<Vec<_>>();
+            lines.push(String::from_utf8_lossy(&line[..line.len() - 1]).into_owned());
+        }
+        Ok(())
+    }
+
+    fn flush_partial(&mut self, lines: &mut Vec<String>) {
+        if !self.partial.is_empty() {
+            lines.push(String::from_utf8_lossy(&self.partial).into_owned());
+            self.partial.clear();
+        }
+    }
+}
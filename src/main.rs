@@ -1,31 +1,268 @@
 use std::{
-    error::Error,
-    io::{self, BufWriter, Write},
-    process::Command,
+    io::{self, Write},
+    thread,
+    time::Duration,
 };
 
-use clap::{Args, Parser, Subcommand};
-use dialoguer::{theme::ColorfulTheme, Confirm, Editor, Input, MultiSelect, Select};
-use duct::cmd;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use console::{style, Color};
+use dialoguer::{theme::ColorfulTheme, Confirm, Editor, Input, Select};
 use prettytable::{row, Table};
-use process_manager::{process, ProcessItem, ProcessManager, SearchArgs};
+use process_manager::{
+    group::ProcessGroup,
+    health::{HealthCheck, HealthStatus, Probe},
+    ipc::Client,
+    logs::{self, LogFollower},
+    parallel, process,
+    profile::Profiles,
+    supervisor::Supervisor,
+    Error, ProcessItem, ProcessManager, ProcessStatus, ReadyStatus, ReloadStatus, RestartPolicy,
+    Result, SearchArgs, StartStatus, StopStatus,
+};
 
 /// Simple program to greet a person
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 #[command(propagate_version = true)]
 struct Cli {
+    /// 配置目录，默认使用 `pm profile use` 选择的配置
+    #[arg(long, global = true)]
+    profile: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    List(SearchArgs),
-    Add(AddArgs),
+    List(ListArgs),
+    Add(Box<AddArgs>),
     Rm(SearchArgs),
-    Start(SearchArgs),
-    Stop(SearchArgs),
+    /// 修改进程配置，默认使用交互式菜单
+    Edit(EditArgs),
+    /// 非交互地修改进程配置
+    Update(Box<UpdateArgs>),
+    Start(StartArgs),
+    /// 停止进程，连同它的子孙进程
+    Stop(StopArgs),
+    Status(StatusArgs),
+    Restart(SearchArgs),
+    Reload(SearchArgs),
+    /// 查看日志
+    Logs(LogsArgs),
+    /// 打印依赖关系树
+    Graph(SearchArgs),
+    /// 以守护进程方式运行，按重启策略重启退出的进程
+    Daemon,
+    /// 管理命名配置
+    #[command(subcommand)]
+    Profile(ProfileCommands),
+    /// 管理进程分组，成员继承分组的默认配置
+    #[command(subcommand)]
+    Group(GroupCommands),
+    /// 日志写入进程，由 pm 启动进程时在内部使用
+    #[command(name = logs::LOG_WRITER_SUBCOMMAND, hide = true)]
+    LogWriter {
+        args: String,
+    },
+}
+
+#[derive(Args)]
+pub struct ListArgs {
+    #[command(flatten)]
+    search_args: SearchArgs,
+    /// 展示展开模板变量后的结果
+    #[arg(long)]
+    expanded: bool,
+}
+
+#[derive(Args)]
+pub struct StartArgs {
+    #[command(flatten)]
+    search_args: SearchArgs,
+    #[command(flatten)]
+    wait_args: WaitArgs,
+    #[command(flatten)]
+    parallel_args: ParallelArgs,
+}
+
+#[derive(Args)]
+pub struct WaitArgs {
+    /// 等待进程就绪：健康检查通过，或检测启动命令有输出
+    #[arg(long)]
+    wait: bool,
+    /// 等待就绪的最长时长，如 60s、2m、500ms
+    #[arg(long, default_value = "60s", value_parser = parse_duration, requires = "wait")]
+    timeout: Duration,
+}
+
+#[derive(Args)]
+pub struct StopArgs {
+    #[command(flatten)]
+    search_args: SearchArgs,
+    /// 只列出会被结束的进程，不停止
+    #[arg(long)]
+    tree_preview: bool,
+    #[command(flatten)]
+    parallel_args: ParallelArgs,
+}
+
+#[derive(Args)]
+pub struct StatusArgs {
+    #[command(flatten)]
+    search_args: SearchArgs,
+    #[command(flatten)]
+    parallel_args: ParallelArgs,
+}
+
+#[derive(Args)]
+pub struct ParallelArgs {
+    /// 同时操作的进程数，启动和停止仍按依赖顺序
+    #[arg(short = 'j', long, default_value_t = parallel::DEFAULT_PARALLEL, value_parser = parse_parallel)]
+    parallel: usize,
+}
+
+#[derive(Subcommand)]
+enum GroupCommands {
+    /// 列出所有分组
+    List,
+    /// 添加分组
+    Add(GroupArgs),
+    /// 删除分组，不删除成员
+    Rm { name: String },
+    /// 修改分组，只修改给出的字段
+    Update(GroupUpdateArgs),
+    /// 启动分组的全部成员
+    Start {
+        name: String,
+        #[command(flatten)]
+        wait_args: WaitArgs,
+        #[command(flatten)]
+        parallel_args: ParallelArgs,
+    },
+    /// 停止分组的全部成员
+    Stop {
+        name: String,
+        #[command(flatten)]
+        parallel_args: ParallelArgs,
+    },
+    /// 查看分组全部成员的状态
+    Status {
+        name: String,
+        #[command(flatten)]
+        parallel_args: ParallelArgs,
+    },
+}
+
+#[derive(Args)]
+pub struct GroupArgs {
+    /// 分组名
+    name: String,
+    /// 成员的唯一程序名，替换全部成员
+    #[arg(short, long)]
+    members: Option<Vec<String>>,
+    /// 备注
+    #[arg(long)]
+    comment: Option<String>,
+    /// 成员默认的工作目录
+    #[arg(long)]
+    cwd: Option<String>,
+    /// 成员默认的环境变量，格式为 KEY=VALUE，可以重复
+    #[arg(short, long, value_parser = parse_env_var)]
+    env: Vec<(String, String)>,
+    /// 成员默认的日志目录，日志写入 <目录>/<唯一程序名>.log
+    #[arg(long)]
+    log_dir: Option<String>,
+    /// 成员默认的重启策略
+    #[arg(long, value_enum)]
+    restart_policy: Option<RestartPolicy>,
+}
+
+#[derive(Args)]
+pub struct GroupUpdateArgs {
+    #[command(flatten)]
+    fields: GroupArgs,
+    /// 添加成员
+    #[arg(long)]
+    add_member: Vec<String>,
+    /// 移除成员
+    #[arg(long)]
+    remove_member: Vec<String>,
+    /// 移除默认的环境变量
+    #[arg(long)]
+    unset_env: Vec<String>,
+}
+
+#[derive(Subcommand)]
+enum ProfileCommands {
+    /// 列出所有配置，当前配置前标记 *
+    List,
+    /// 切换当前配置，配置不存在时创建
+    Use { name: String },
+}
+
+#[derive(Args)]
+pub struct EditArgs {
+    /// 唯一程序名
+    name: String,
+    /// 在 $EDITOR 中以 JSON5 编辑
+    #[arg(long)]
+    editor: bool,
+}
+
+#[derive(Args)]
+pub struct UpdateArgs {
+    /// 要修改的唯一程序名
+    #[arg(value_name = "NAME")]
+    target: String,
+    /// 添加标签
+    #[arg(long)]
+    add_tag: Vec<String>,
+    /// 移除标签
+    #[arg(long)]
+    remove_tag: Vec<String>,
+    /// 移除环境变量
+    #[arg(long)]
+    unset_env: Vec<String>,
+    /// 继承 pm 的环境变量，与 --clear-env 相反
+    #[arg(long, conflicts_with = "clear_env")]
+    no_clear_env: bool,
+    /// 启动时清空日志，与 --log-append 相反
+    #[arg(long, conflicts_with = "log_append")]
+    no_log_append: bool,
+    /// 不压缩历史日志，与 --log-compress 相反
+    #[arg(long, conflicts_with = "log_compress")]
+    no_log_compress: bool,
+    /// 不使用单独的 cgroup，与 --cgroup 相反
+    #[arg(long, conflicts_with = "cgroup")]
+    no_cgroup: bool,
+    /// 删除进程自己的配置，改为继承所在分组的默认配置，可以重复
+    #[arg(long, value_enum)]
+    inherit: Vec<InheritField>,
+    /// 其余参数与 add 相同，--name 用于改名，--tags 替换全部标签
+    #[command(flatten)]
+    fields: AddArgs,
+}
+
+/// 可以继承分组默认配置的字段
+#[derive(Clone, Copy, ValueEnum)]
+enum InheritField {
+    Cwd,
+    LogPath,
+    RestartPolicy,
+}
+
+/// `-n` 与 `tail` 一致表示行数，按名称筛选只能使用 `--name`
+#[derive(Args)]
+#[command(mut_arg("name", |arg| arg.short(None)))]
+pub struct LogsArgs {
+    #[command(flatten)]
+    search_args: SearchArgs,
+    /// 输出最后多少行
+    #[arg(short = 'n', long, default_value_t = 10)]
+    lines: usize,
+    /// 持续输出新增的日志
+    #[arg(short, long)]
+    follow: bool,
 }
 
 #[derive(Args)]
@@ -51,12 +288,150 @@ pub struct AddArgs {
     /// 健康检测命令
     #[arg(long)]
     detection_start_cmd: Option<String>,
-    /// pid查询命令
+    /// pid查询命令，用于停止和查看不是由 pm 启动的进程
     #[arg(long)]
     pid_search_cmd: Option<String>,
     /// 备注
     #[arg(long)]
     comment: Option<String>,
+    /// 停止信号
+    #[arg(long)]
+    stop_signal: Option<String>,
+    /// 停止等待时长（秒），超时后强制结束
+    #[arg(long)]
+    stop_timeout: Option<u64>,
+    /// 重新加载信号
+    #[arg(long)]
+    reload_signal: Option<String>,
+    /// 重新加载命令，配置后代替发送重新加载信号
+    #[arg(long)]
+    reload_cmd: Option<String>,
+    /// 重启策略
+    #[arg(long, value_enum)]
+    restart_policy: Option<RestartPolicy>,
+    /// 最大连续重启次数
+    #[arg(long)]
+    max_retries: Option<u32>,
+    /// 首次重启前的等待时长（秒），之后每次翻倍
+    #[arg(long)]
+    restart_backoff: Option<u64>,
+    /// 启动时追加写入日志，默认清空
+    #[arg(long)]
+    log_append: bool,
+    /// 单个日志文件的最大字节数，超过后轮转
+    #[arg(long)]
+    log_max_size: Option<u64>,
+    /// 单个日志文件的最长写入时长（秒），超过后轮转
+    #[arg(long)]
+    log_max_age: Option<u64>,
+    /// 保留的历史日志个数
+    #[arg(long)]
+    log_keep: Option<usize>,
+    /// 用 gzip 压缩历史日志
+    #[arg(long)]
+    log_compress: bool,
+    /// 工作目录
+    #[arg(long)]
+    cwd: Option<String>,
+    /// 环境变量，格式为 KEY=VALUE，可以重复
+    #[arg(short, long, value_parser = parse_env_var)]
+    env: Vec<(String, String)>,
+    /// dotenv 格式的环境变量文件，可以重复
+    #[arg(long)]
+    env_file: Option<Vec<String>>,
+    /// 不继承 pm 的环境变量
+    #[arg(long)]
+    clear_env: bool,
+    /// 运行的用户，名称或 uid
+    #[arg(long)]
+    user: Option<String>,
+    /// 运行的组，名称或 gid，默认为用户的主组
+    #[arg(long)]
+    group: Option<String>,
+    /// 附加组，默认为用户所属的全部组
+    #[arg(long)]
+    supplementary_groups: Option<Vec<String>>,
+    /// 八进制的文件创建掩码，如 027
+    #[arg(long)]
+    umask: Option<String>,
+    /// 最大打开文件数，数字或 unlimited
+    #[arg(long)]
+    limit_nofile: Option<String>,
+    /// 最大虚拟内存，字节数，可以带 K/M/G 后缀，或 unlimited
+    #[arg(long)]
+    limit_as: Option<String>,
+    /// 最大 core 文件大小，字节数，可以带 K/M/G 后缀，或 unlimited
+    #[arg(long)]
+    limit_core: Option<String>,
+    /// nice 值，-20 到 19
+    #[arg(long, allow_hyphen_values = true)]
+    nice: Option<i32>,
+    /// OOM 分数调整，-1000 到 1000
+    #[arg(long, allow_hyphen_values = true)]
+    oom_score_adj: Option<i32>,
+    /// 允许运行的 CPU 列表，如 0-3,6
+    #[arg(long)]
+    cpu_affinity: Option<String>,
+    /// 放入单独的 cgroup，停止时结束整个进程树
+    #[arg(long)]
+    cgroup: bool,
+    /// cgroup 内存上限，字节数，可以带 K/M/G 后缀，或 max
+    #[arg(long)]
+    memory_max: Option<String>,
+    /// cgroup CPU 上限，核数如 1.5，或 max
+    #[arg(long)]
+    cpu_max: Option<String>,
+    /// cgroup 最大进程数，或 max
+    #[arg(long)]
+    pids_max: Option<String>,
+    /// 依赖的进程，唯一程序名或 tag:标签
+    #[arg(long)]
+    depends_on: Option<Vec<String>>,
+    /// 健康检查，格式为 类型:参数，如 exec:命令、tcp:[主机:]端口、http:地址、pid-file:路径、log-regex:正则
+    #[arg(long)]
+    health: Option<Probe>,
+    /// 健康检查间隔（秒）
+    #[arg(long)]
+    health_interval: Option<u64>,
+    /// 单次健康检查的超时（秒）
+    #[arg(long)]
+    health_timeout: Option<u64>,
+    /// 连续失败多少次视为不健康
+    #[arg(long)]
+    health_retries: Option<u32>,
+}
+
+/// 解析 `KEY=VALUE` 格式的环境变量
+fn parse_env_var(s: &str) -> std::result::Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("格式应为 KEY=VALUE：{}", s)),
+    }
+}
+
+/// 解析时长，支持 ms、s、m、h 后缀，不带后缀时单位为秒
+fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let invalid = || format!("无效的时长：{}", s);
+    let (number, unit) = match s.find(|it: char| !it.is_ascii_digit() && it != '.') {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let number = number.parse::<f64>().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(invalid()),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+fn parse_parallel(s: &str) -> std::result::Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(parallel) if parallel > 0 => Ok(parallel),
+        _ => Err(format!("并发数必须是正整数：{}", s)),
+    }
 }
 
 fn main() {
@@ -70,17 +445,137 @@ fn main() {
     ##
      */
     let cli = Cli::parse();
-    //~/.process_manager
-    let mut pm = ProcessManager::new(".");
+    if let Err(e) = run(cli) {
+        eprintln!("错误: {}", e);
+        std::process::exit(exit_code(&e));
+    }
+}
+
+/// 不同错误对应的退出码，避开 clap 参数错误使用的 2
+fn exit_code(e: &Error) -> i32 {
+    match e {
+        Error::Os(_) => 1,
+        Error::ConfigParse { .. } => 3,
+        Error::Invalid(_) => 4,
+        Error::NotFound(_) => 5,
+        Error::DuplicateName(_) => 6,
+        Error::Io { .. } => 7,
+        Error::Spawn { .. } => 8,
+        Error::Detection { .. } => 9,
+        Error::Daemon(_) => 10,
+        Error::Conflict { .. } => 11,
+        Error::Permission(_) => 12,
+        Error::NotReady(_) => 13,
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    // 日志写入进程不读取配置
+    if let Commands::LogWriter { ref args } = cli.command {
+        return logs::run_log_writer(args);
+    }
+    if let Commands::Profile(ref profile_command) = cli.command {
+        return run_profile_command(profile_command);
+    }
+    let profile_path = Profiles::resolve(cli.profile.as_deref())?;
+    let mut pm = ProcessManager::new(&profile_path)?;
+    // 守护进程在运行时由它来操作进程
+    let daemon = Client::connect(&profile_path);
+    for (name, record) in pm.stale_runs() {
+        println!(
+            "{}(pid:{}) 已不在运行，清理运行记录 {}",
+            name, record.pid, record.run_id
+        );
+    }
 
     match cli.command {
-        Commands::List(search_args) => {
-            let processes = pm.list(search_args);
-            print_processes(processes)
+        Commands::List(list_args) => {
+            let processes = pm.list(list_args.search_args);
+            if !list_args.expanded {
+                print_processes(processes);
+                return Ok(());
+            }
+
+            let expanded = processes
+                .into_iter()
+                .map(|it| {
+                    pm.expand(it).unwrap_or_else(|e| {
+                        eprintln!("{} 模板展开失败: {}", it.name, e);
+                        it.clone()
+                    })
+                })
+                .collect::<Vec<_>>();
+            print_processes(expanded.iter().collect())
         }
         Commands::Add(add_args) => {
-            let pi = build_process_item(add_args);
-            pm.add(pi)
+            let pi = build_process_item(*add_args);
+            pm.add(pi)?
+        }
+        Commands::Edit(edit_args) => {
+            let ele = pm
+                .get(&edit_args.name)
+                .cloned()
+                .ok_or_else(|| Error::NotFound(edit_args.name.clone()))?;
+            let pi = if edit_args.editor {
+                match edit_in_editor(&ele)? {
+                    Some(pi) => pi,
+                    None => {
+                        println!("未修改");
+                        return Ok(());
+                    }
+                }
+            } else {
+                build_process_item(seed_add_args(&ele))
+            };
+            pm.update(&edit_args.name, pi)?
+        }
+        Commands::Update(update_args) => {
+            let UpdateArgs {
+                target,
+                add_tag,
+                remove_tag,
+                unset_env,
+                no_clear_env,
+                no_log_append,
+                no_log_compress,
+                no_cgroup,
+                inherit,
+                fields,
+            } = *update_args;
+            let mut pi = pm
+                .get(&target)
+                .cloned()
+                .ok_or_else(|| Error::NotFound(target.clone()))?;
+            apply_add_args(&mut pi, fields);
+            for tag in add_tag {
+                if !pi.tags.contains(&tag) {
+                    pi.tags.push(tag);
+                }
+            }
+            pi.tags.retain(|it| !remove_tag.contains(it));
+            for key in unset_env {
+                pi.env.remove(&key);
+            }
+            if no_clear_env {
+                pi.clear_env = false;
+            }
+            if no_log_append {
+                pi.log_append = false;
+            }
+            if no_log_compress {
+                pi.log_rotation.compress = false;
+            }
+            if no_cgroup {
+                pi.cgroup.enabled = false;
+            }
+            for field in inherit {
+                match field {
+                    InheritField::Cwd => pi.cwd = None,
+                    InheritField::LogPath => pi.log_path = None,
+                    InheritField::RestartPolicy => pi.restart_policy = None,
+                }
+            }
+            pm.update(&target, pi)?
         }
         Commands::Rm(search_args) => {
             let processes = pm.list(search_args);
@@ -88,18 +583,95 @@ fn main() {
                 .iter()
                 .map(|it| it.name.clone())
                 .collect::<Vec<_>>();
-            pm.remove(collect)
+            pm.remove(collect)?
         }
-        Commands::Start(search_args) => {
+        Commands::Start(start_args) => {
+            let processes = pm.list(start_args.search_args);
+            let collect = processes
+                .iter()
+                .map(|it| it.name.clone())
+                .collect::<Vec<_>>();
+            start_processes(
+                &mut pm,
+                daemon.as_ref(),
+                collect,
+                &start_args.wait_args,
+                start_args.parallel_args.parallel,
+            )?
+        }
+        Commands::Stop(stop_args) => {
+            let processes = pm.list(stop_args.search_args);
+            let collect = processes
+                .iter()
+                .map(|it| it.name.clone())
+                .collect::<Vec<_>>();
+            if stop_args.tree_preview {
+                print_tree_preview(pm.stop_preview(collect));
+                return Ok(());
+            }
+            stop_processes(
+                &mut pm,
+                daemon.as_ref(),
+                collect,
+                stop_args.parallel_args.parallel,
+            )?
+        }
+        Commands::Status(status_args) => {
+            let processes = pm.list(status_args.search_args);
+            let collect = processes
+                .iter()
+                .map(|it| it.name.clone())
+                .collect::<Vec<_>>();
+            show_statuses(
+                &mut pm,
+                daemon.as_ref(),
+                collect,
+                status_args.parallel_args.parallel,
+            )?
+        }
+        Commands::Restart(search_args) => {
             let processes = pm.list(search_args);
             let collect = processes
                 .iter()
                 .map(|it| it.name.clone())
                 .collect::<Vec<_>>();
-            pm.start(collect)
+            let results = match daemon {
+                Some(ref client) => client.restart(collect)?,
+                None => pm.restart(collect),
+            };
+            print_restart_results(results)
         }
-        Commands::Stop(search_args) => {}
-    }
+        Commands::Reload(search_args) => {
+            let processes = pm.list(search_args);
+            let collect = processes
+                .iter()
+                .map(|it| it.name.clone())
+                .collect::<Vec<_>>();
+            let results = match daemon {
+                Some(ref client) => client.reload(collect)?,
+                None => pm.reload(collect),
+            };
+            print_reload_results(results)
+        }
+        Commands::Logs(logs_args) => {
+            let processes = pm.list(logs_args.search_args);
+            let collect = processes
+                .iter()
+                .map(|it| it.name.clone())
+                .collect::<Vec<_>>();
+            let log_paths = match daemon {
+                Some(ref client) => client.logs(collect)?,
+                None => pm.logs(collect),
+            };
+            print_logs(log_paths, logs_args.lines, logs_args.follow)
+        }
+        Commands::Graph(search_args) => print_graph(&pm, pm.list(search_args)),
+        Commands::Group(group_command) => {
+            run_group_command(&mut pm, daemon.as_ref(), group_command)?
+        }
+        Commands::Daemon => Supervisor::new(&profile_path).run()?,
+        Commands::LogWriter { .. } | Commands::Profile(_) => unreachable!(),
+    };
 
     // cmd!("sleep", "100")
     //     .before_spawn(|cmd| {
@@ -120,7 +692,202 @@ fn main() {
     //     .unwrap();
 
     // println!("{}", template_options[selection].to_string());
+
+    Ok(())
 }
+/// 启动进程，需要时等待就绪，有进程未就绪时返回错误
+fn start_processes(
+    pm: &mut ProcessManager,
+    daemon: Option<&Client>,
+    names: Vec<String>,
+    wait_args: &WaitArgs,
+    parallel: usize,
+) -> Result<()> {
+    pm.set_parallel(parallel);
+    let results = match daemon {
+        Some(client) => client.start(names.clone(), parallel)?,
+        None => pm.start(names.clone()),
+    };
+    print_start_results(results);
+    if !wait_args.wait {
+        return Ok(());
+    }
+
+    let results = pm.wait_ready(names, wait_args.timeout);
+    let not_ready = results
+        .iter()
+        .filter(|(_, status)| !matches!(status, ReadyStatus::Ready(_)))
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    print_ready_results(results);
+    if !not_ready.is_empty() {
+        return Err(Error::NotReady(not_ready));
+    }
+    Ok(())
+}
+
+fn stop_processes(
+    pm: &mut ProcessManager,
+    daemon: Option<&Client>,
+    names: Vec<String>,
+    parallel: usize,
+) -> Result<()> {
+    pm.set_parallel(parallel);
+    let results = match daemon {
+        Some(client) => client.stop(names, parallel)?,
+        None => pm.stop(names),
+    };
+    print_stop_results(results);
+    Ok(())
+}
+
+fn show_statuses(
+    pm: &mut ProcessManager,
+    daemon: Option<&Client>,
+    names: Vec<String>,
+    parallel: usize,
+) -> Result<()> {
+    pm.set_parallel(parallel);
+    let statuses = match daemon {
+        Some(client) => client.status(names, parallel)?,
+        None => pm.status(names),
+    };
+    print_statuses(statuses);
+    Ok(())
+}
+
+fn run_group_command(
+    pm: &mut ProcessManager,
+    daemon: Option<&Client>,
+    group_command: GroupCommands,
+) -> Result<()> {
+    match group_command {
+        GroupCommands::List => print_groups(pm.groups()),
+        GroupCommands::Add(args) => {
+            let mut group = ProcessGroup {
+                name: args.name.clone(),
+                ..Default::default()
+            };
+            apply_group_args(&mut group, args);
+            pm.add_group(group)?
+        }
+        GroupCommands::Rm { name } => pm.remove_group(&name)?,
+        GroupCommands::Update(args) => {
+            let GroupUpdateArgs {
+                fields,
+                add_member,
+                remove_member,
+                unset_env,
+            } = args;
+            let name = fields.name.clone();
+            let mut group = pm
+                .get_group(&name)
+                .cloned()
+                .ok_or_else(|| Error::Invalid(format!("未找到分组：{}", name)))?;
+            apply_group_args(&mut group, fields);
+            for member in add_member {
+                if !group.members.contains(&member) {
+                    group.members.push(member);
+                }
+            }
+            group.members.retain(|it| !remove_member.contains(it));
+            for key in unset_env {
+                group.env.remove(&key);
+            }
+            pm.update_group(&name, group)?
+        }
+        GroupCommands::Start {
+            name,
+            wait_args,
+            parallel_args,
+        } => {
+            let members = pm.group_members(&name)?;
+            start_processes(pm, daemon, members, &wait_args, parallel_args.parallel)?
+        }
+        GroupCommands::Stop {
+            name,
+            parallel_args,
+        } => {
+            let members = pm.group_members(&name)?;
+            stop_processes(pm, daemon, members, parallel_args.parallel)?
+        }
+        GroupCommands::Status {
+            name,
+            parallel_args,
+        } => {
+            let members = pm.group_members(&name)?;
+            show_statuses(pm, daemon, members, parallel_args.parallel)?
+        }
+    }
+    Ok(())
+}
+
+/// 只修改命令行中给出的字段，环境变量与已有的合并
+fn apply_group_args(group: &mut ProcessGroup, args: GroupArgs) {
+    if let Some(members) = args.members {
+        group.members = members;
+    }
+    if let Some(comment) = args.comment {
+        group.comment = comment;
+    }
+    if let Some(cwd) = args.cwd {
+        group.cwd = cwd;
+    }
+    group.env.extend(args.env);
+    if let Some(log_dir) = args.log_dir {
+        group.log_dir = log_dir;
+    }
+    if let Some(restart_policy) = args.restart_policy {
+        group.restart_policy = Some(restart_policy);
+    }
+}
+
+fn print_groups(groups: &[ProcessGroup]) {
+    let mut table = Table::new();
+
+    table.add_row(row!["分组名", "成员", "默认配置", "备注"]);
+
+    for group in groups {
+        let mut defaults = vec![];
+        if !group.cwd.is_empty() {
+            defaults.push(format!("工作目录 {}", group.cwd));
+        }
+        if !group.log_dir.is_empty() {
+            defaults.push(format!("日志目录 {}", group.log_dir));
+        }
+        if let Some(restart_policy) = group.restart_policy {
+            defaults.push(format!("重启策略 {}", restart_policy));
+        }
+        defaults.extend(group.env.iter().map(|(k, v)| format!("{}={}", k, v)));
+        table.add_row(row![
+            group.name,
+            group.members.join("\n"),
+            defaults.join("\n"),
+            group.comment,
+        ]);
+    }
+
+    table.printstd();
+}
+
+fn run_profile_command(profile_command: &ProfileCommands) -> Result<()> {
+    let profiles = Profiles::from_env()?;
+    match profile_command {
+        ProfileCommands::List => {
+            let current = profiles.current();
+            for name in profiles.list()? {
+                let marker = if name == current { "*" } else { " " };
+                println!("{} {}\t{}", marker, name, profiles.dir(&name)?.display());
+            }
+        }
+        ProfileCommands::Use { name } => {
+            let dir = profiles.switch(name)?;
+            println!("已切换到配置 {}：{}", name, dir);
+        }
+    }
+    Ok(())
+}
+
 fn print_processes(processes: Vec<&ProcessItem>) {
     // Create the table
     let mut table = Table::new();
@@ -133,18 +900,38 @@ fn print_processes(processes: Vec<&ProcessItem>) {
         "进程类型",
         "日志路径",
         "检测启动命令",
+        "工作目录",
+        "环境变量",
+        "运行用户",
+        "资源限制",
+        "健康检查",
         "备注"
     ]);
 
     // 打印每个进程的信息
     for process in processes {
+        // 环境变量文件在前，env 中的变量覆盖文件中的同名变量
+        let mut env = process
+            .env_files
+            .iter()
+            .map(|it| format!("@{}", it))
+            .chain(process.env.iter().map(|(k, v)| format!("{}={}", k, v)))
+            .collect::<Vec<_>>();
+        if process.clear_env {
+            env.insert(0, "(不继承环境变量)".to_string());
+        }
         table.add_row(row![
             process.name,
             process.command,
             process.tags.join(", "),
             process.process_type,
-            process.log_path,
+            process.log_path(),
             process.detection_start_cmd,
+            process.cwd(),
+            env.join("\n"),
+            format_run_as(process),
+            format_limits(process),
+            format_health_check(process.health.as_ref()),
             process.comment,
         ]);
     }
@@ -152,6 +939,421 @@ fn print_processes(processes: Vec<&ProcessItem>) {
     table.printstd();
 }
 
+/// 运行用户，如 `app:app +docker umask 027`
+fn format_run_as(process: &ProcessItem) -> String {
+    let mut run_as = process.user.clone();
+    if !process.group.is_empty() {
+        run_as.push(':');
+        run_as.push_str(&process.group);
+    }
+    for group in &process.supplementary_groups {
+        run_as.push_str(" +");
+        run_as.push_str(group);
+    }
+    if !process.umask.is_empty() {
+        run_as.push_str(" umask ");
+        run_as.push_str(&process.umask);
+    }
+    run_as.trim_start().to_string()
+}
+
+/// 配置的资源限制和 cgroup 限制，每项一行
+fn format_limits(process: &ProcessItem) -> String {
+    let limits = &process.limits;
+    let mut lines = vec![];
+    for (name, value) in [
+        ("nofile", &limits.nofile),
+        ("as", &limits.address_space),
+        ("core", &limits.core),
+        ("cpu", &limits.cpu_affinity),
+    ] {
+        if !value.is_empty() {
+            lines.push(format!("{}={}", name, value));
+        }
+    }
+    if let Some(nice) = limits.nice {
+        lines.push(format!("nice={}", nice));
+    }
+    if let Some(adj) = limits.oom_score_adj {
+        lines.push(format!("oom={}", adj));
+    }
+    if process.cgroup.is_enabled() {
+        lines.push("cgroup".to_string());
+    }
+    for (name, value) in [
+        ("memory.max", &process.cgroup.memory_max),
+        ("cpu.max", &process.cgroup.cpu_max),
+        ("pids.max", &process.cgroup.pids_max),
+    ] {
+        if !value.is_empty() {
+            lines.push(format!("{}={}", name, value));
+        }
+    }
+    lines.join("\n")
+}
+
+/// 健康检查方式和检查参数
+fn format_health_check(health: Option<&HealthCheck>) -> String {
+    match health {
+        Some(health) => format!(
+            "{}\n每 {}s，超时 {}s，重试 {} 次",
+            health.probe, health.interval, health.timeout, health.retries
+        ),
+        None => String::new(),
+    }
+}
+
+/// 以选中的进程中没有被其他选中进程依赖的为根，打印依赖树
+fn print_graph(pm: &ProcessManager, processes: Vec<&ProcessItem>) {
+    let roots = processes.iter().filter(|ele| {
+        !processes.iter().any(|other| {
+            pm.dependencies(other)
+                .iter()
+                .any(|dep| dep.name == ele.name)
+        })
+    });
+    for root in roots {
+        println!("{}", root.name);
+        print_dependencies(pm, root, "");
+    }
+}
+
+fn print_dependencies(pm: &ProcessManager, ele: &ProcessItem, prefix: &str) {
+    let deps = pm.dependencies(ele);
+    for (i, dep) in deps.iter().enumerate() {
+        let last = i + 1 == deps.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        println!("{}{}{}", prefix, branch, dep.name);
+        print_dependencies(pm, dep, &format!("{}{}", prefix, indent));
+    }
+}
+
+fn print_ready_results(results: Vec<(String, ReadyStatus)>) {
+    let mut table = Table::new();
+
+    table.add_row(row!["唯一程序名", "就绪结果"]);
+
+    for (name, status) in results {
+        table.add_row(row![name, status]);
+    }
+
+    table.printstd();
+}
+
+fn print_start_results(results: Vec<(String, StartStatus)>) {
+    let mut table = Table::new();
+
+    table.add_row(row!["唯一程序名", "启动结果"]);
+
+    for (name, status) in results {
+        table.add_row(row![name, status]);
+    }
+
+    table.printstd();
+}
+
+fn print_stop_results(results: Vec<(String, StopStatus)>) {
+    let mut table = Table::new();
+
+    table.add_row(row!["唯一程序名", "停止结果"]);
+
+    for (name, status) in results {
+        table.add_row(row![name, status]);
+    }
+
+    table.printstd();
+}
+
+fn print_restart_results(results: Vec<(String, StopStatus, StartStatus)>) {
+    let mut table = Table::new();
+
+    table.add_row(row!["唯一程序名", "停止结果", "启动结果"]);
+
+    for (name, stop_status, start_status) in results {
+        table.add_row(row![name, stop_status, start_status]);
+    }
+
+    table.printstd();
+}
+
+fn print_reload_results(results: Vec<(String, ReloadStatus)>) {
+    let mut table = Table::new();
+
+    table.add_row(row!["唯一程序名", "重新加载结果"]);
+
+    for (name, status) in results {
+        table.add_row(row![name, status]);
+    }
+
+    table.printstd();
+}
+
+fn print_logs(log_paths: Vec<(String, String)>, lines: usize, follow: bool) {
+    const COLORS: [Color; 6] = [
+        Color::Cyan,
+        Color::Green,
+        Color::Yellow,
+        Color::Magenta,
+        Color::Blue,
+        Color::Red,
+    ];
+
+    let log_paths = log_paths
+        .into_iter()
+        .filter(|(name, log_path)| {
+            if log_path.is_empty() {
+                eprintln!("{} 未配置日志路径", name);
+            }
+            !log_path.is_empty()
+        })
+        .collect::<Vec<_>>();
+    let width = log_paths
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let prefixes = log_paths
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| {
+            style(format!("{:width$} |", name, width = width))
+                .fg(COLORS[i % COLORS.len()])
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (i, (name, log_path)) in log_paths.iter().enumerate() {
+        match logs::tail_lines(log_path, lines) {
+            Ok(tail) => {
+                for line in tail {
+                    let _ = writeln!(out, "{} {}", prefixes[i], line);
+                }
+            }
+            Err(e) => eprintln!("{} 读取日志失败 {}: {}", name, log_path, e),
+        }
+    }
+    let _ = out.flush();
+    drop(out);
+
+    if !follow {
+        return;
+    }
+
+    let mut followers = log_paths
+        .iter()
+        .map(|(name, log_path)| LogFollower::new(name, log_path))
+        .collect::<Vec<_>>();
+    loop {
+        let mut out = stdout.lock();
+        for (i, follower) in followers.iter_mut().enumerate() {
+            match follower.poll() {
+                Ok(new_lines) => {
+                    for line in new_lines {
+                        let _ = writeln!(out, "{} {}", prefixes[i], line);
+                    }
+                }
+                Err(e) => eprintln!("{} 读取日志失败: {}", follower.name, e),
+            }
+        }
+        let _ = out.flush();
+        drop(out);
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+fn print_tree_preview(previews: Vec<(String, Result<Vec<process::TreeProcess>>)>) {
+    let mut table = Table::new();
+
+    table.add_row(row!["唯一程序名", "PID", "父进程", "会话", "命令"]);
+
+    for (name, tree) in previews {
+        match tree {
+            Ok(tree) if tree.is_empty() => {
+                table.add_row(row![name, "-", "-", "-", "未运行"]);
+            }
+            Ok(tree) => {
+                for it in tree {
+                    table.add_row(row![name, it.pid, it.ppid, it.sid, it.command]);
+                }
+            }
+            Err(e) => {
+                table.add_row(row![name, "-", "-", "-", format!("查询失败: {}", e)]);
+            }
+        }
+    }
+
+    table.printstd();
+}
+
+fn print_statuses(statuses: Vec<ProcessStatus>) {
+    let mut table = Table::new();
+
+    table.add_row(row![
+        "唯一程序名",
+        "状态",
+        "健康",
+        "PID",
+        "运行时长",
+        "CPU%",
+        "内存",
+        "线程数",
+        "工作目录",
+        "资源限制",
+        "cgroup",
+        "最近退出码"
+    ]);
+
+    for status in statuses {
+        let last_exit = match status.last_exit {
+            Some(ref exit) => {
+                let code = exit
+                    .exit_code
+                    .map(|it| it.to_string())
+                    .unwrap_or_else(|| "未知".to_string());
+                if exit.unhealthy {
+                    format!("{} (健康检查失败)", code)
+                } else {
+                    code
+                }
+            }
+            None => "-".to_string(),
+        };
+
+        if !status.is_running() {
+            let state = if status.is_crash_loop() {
+                "崩溃循环"
+            } else {
+                "已停止"
+            };
+            table.add_row(row![
+                status.name,
+                state,
+                "-",
+                "-",
+                "-",
+                "-",
+                "-",
+                "-",
+                "-",
+                "-",
+                "-",
+                last_exit
+            ]);
+            continue;
+        }
+
+        let pids = status
+            .pids
+            .iter()
+            .map(|it| it.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let run_time = status.stats.iter().map(|it| it.run_time).max().unwrap_or(0);
+        let cpu_usage = status.stats.iter().map(|it| it.cpu_usage).sum::<f32>();
+        let memory = status.stats.iter().map(|it| it.memory).sum::<u64>();
+        let threads = status.stats.iter().map(|it| it.threads).sum::<usize>();
+        let mut cwds = status
+            .stats
+            .iter()
+            .filter_map(|it| it.cwd.clone())
+            .collect::<Vec<_>>();
+        cwds.dedup();
+        // 同一程序的进程通常继承相同的限制，只显示第一个
+        let limits = status
+            .stats
+            .iter()
+            .find_map(|it| it.limits.as_ref())
+            .map(|it| {
+                format!(
+                    "nofile={}\nas={}\ncore={}",
+                    it.nofile, it.address_space, it.core
+                )
+            })
+            .unwrap_or_default();
+
+        table.add_row(row![
+            status.name,
+            "运行中",
+            match &status.health {
+                Some(HealthStatus::Healthy) => style("健康").green().to_string(),
+                Some(health) => style(health).red().to_string(),
+                None => "-".to_string(),
+            },
+            pids,
+            format_duration(run_time),
+            format!("{:.1}", cpu_usage),
+            format_bytes(memory),
+            threads,
+            cwds.join("\n"),
+            limits,
+            format_cgroup(&status),
+            last_exit
+        ]);
+    }
+
+    table.printstd();
+}
+
+/// cgroup 中的进程数和资源用量，cgroup 不可用时显示原因
+fn format_cgroup(status: &ProcessStatus) -> String {
+    let mut lines = vec![];
+    if let Some(cgroup) = &status.cgroup {
+        lines.push(format!("进程数 {}", cgroup.processes));
+        if let Some(memory) = cgroup.memory_current {
+            lines.push(format!("内存 {}", format_bytes(memory)));
+        }
+        if let Some(usage) = cgroup.cpu_usage_usec {
+            lines.push(format!("CPU {:.1}s", usage as f64 / 1_000_000.0));
+        }
+    }
+    if let Some(warning) = &status.cgroup_warning {
+        lines.push(format!("警告: {}", warning));
+    }
+    if lines.is_empty() {
+        "-".to_string()
+    } else {
+        lines.join("\n")
+    }
+}
+
+/// 格式化时长，如 `2d 03:04:05`
+fn format_duration(seconds: u64) -> String {
+    let days = seconds / 86400;
+    let time = format!(
+        "{:02}:{:02}:{:02}",
+        seconds % 86400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    );
+    if days > 0 {
+        format!("{}d {}", days, time)
+    } else {
+        time
+    }
+}
+
+/// 格式化字节数，如 `12.3 MiB`
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
 fn build_process_item(mut add_args: AddArgs) -> ProcessItem {
     // 获取pid对应的命令
     if let Some(pid) = add_args.pid {
@@ -328,16 +1530,229 @@ fn build_process_item(mut add_args: AddArgs) -> ProcessItem {
         }
     }
 
-    // 创建并返回ProcessItem
-    ProcessItem {
-        name: add_args.name.unwrap(),
-        tags: add_args.tags.unwrap_or(vec![]),
-        command: add_args.command.unwrap(),
-        process_type: add_args.process_type.unwrap_or_else(|| "".to_string()),
-        log_path: add_args.log_path.unwrap_or_else(|| "".to_string()),
-        detection_start_cmd: add_args
-            .detection_start_cmd
-            .unwrap_or_else(|| "".to_string()),
-        comment: add_args.comment.unwrap_or_else(|| "".to_string()),
+    // 在默认配置上应用输入的值，pid 对应的命令已经读取过
+    add_args.pid = None;
+    let mut ele = ProcessItem::default();
+    apply_add_args(&mut ele, add_args);
+    ele
+}
+
+/// 设置检查方式后才能设置检查参数，已有的检查参数在更换检查方式时保留
+fn apply_health_args(
+    health: Option<HealthCheck>,
+    probe: Option<Probe>,
+    interval: Option<u64>,
+    timeout: Option<u64>,
+    retries: Option<u32>,
+) -> Option<HealthCheck> {
+    let mut health = match (health, probe) {
+        (Some(health), Some(probe)) => HealthCheck { probe, ..health },
+        (None, Some(probe)) => HealthCheck::new(probe),
+        (Some(health), None) => health,
+        (None, None) => {
+            if interval.is_some() || timeout.is_some() || retries.is_some() {
+                println!("未配置健康检查，忽略健康检查参数");
+            }
+            return None;
+        }
+    };
+    if let Some(interval) = interval {
+        health.interval = interval;
+    }
+    if let Some(timeout) = timeout {
+        health.timeout = timeout;
+    }
+    if let Some(retries) = retries {
+        health.retries = retries;
+    }
+    Some(health)
+}
+
+/// 用已有的配置填充 add 参数，作为交互式修改的初始值
+fn seed_add_args(ele: &ProcessItem) -> AddArgs {
+    AddArgs {
+        pid: None,
+        tags: Some(ele.tags.clone()),
+        name: Some(ele.name.clone()),
+        command: Some(ele.command.clone()),
+        process_type: Some(ele.process_type.clone()),
+        log_path: ele.log_path.clone(),
+        detection_start_cmd: Some(ele.detection_start_cmd.clone()),
+        pid_search_cmd: Some(ele.pid_search_cmd.clone()),
+        comment: Some(ele.comment.clone()),
+        stop_signal: Some(ele.stop_signal.clone()),
+        stop_timeout: Some(ele.stop_timeout),
+        reload_signal: Some(ele.reload_signal.clone()),
+        reload_cmd: Some(ele.reload_cmd.clone()),
+        restart_policy: ele.restart_policy,
+        max_retries: Some(ele.max_retries),
+        restart_backoff: Some(ele.restart_backoff),
+        log_append: ele.log_append,
+        log_max_size: Some(ele.log_rotation.max_size),
+        log_max_age: Some(ele.log_rotation.max_age),
+        log_keep: Some(ele.log_rotation.keep),
+        log_compress: ele.log_rotation.compress,
+        cwd: ele.cwd.clone(),
+        env: ele.env.clone().into_iter().collect(),
+        env_file: Some(ele.env_files.clone()),
+        clear_env: ele.clear_env,
+        user: Some(ele.user.clone()),
+        group: Some(ele.group.clone()),
+        supplementary_groups: Some(ele.supplementary_groups.clone()),
+        umask: Some(ele.umask.clone()),
+        limit_nofile: Some(ele.limits.nofile.clone()),
+        limit_as: Some(ele.limits.address_space.clone()),
+        limit_core: Some(ele.limits.core.clone()),
+        nice: ele.limits.nice,
+        oom_score_adj: ele.limits.oom_score_adj,
+        cpu_affinity: Some(ele.limits.cpu_affinity.clone()),
+        cgroup: ele.cgroup.enabled,
+        memory_max: Some(ele.cgroup.memory_max.clone()),
+        cpu_max: Some(ele.cgroup.cpu_max.clone()),
+        pids_max: Some(ele.cgroup.pids_max.clone()),
+        depends_on: Some(ele.depends_on.clone()),
+        health: ele.health.as_ref().map(|it| it.probe.clone()),
+        health_interval: ele.health.as_ref().map(|it| it.interval),
+        health_timeout: ele.health.as_ref().map(|it| it.timeout),
+        health_retries: ele.health.as_ref().map(|it| it.retries),
+    }
+}
+
+/// 只修改命令行中给出的字段
+fn apply_add_args(ele: &mut ProcessItem, add_args: AddArgs) {
+    if let Some(pid) = add_args.pid {
+        match process::get_process_info(pid) {
+            Some((cmd, ..)) => ele.command = cmd,
+            None => println!("未找到pid:{}对应的进程", pid),
+        }
+    }
+    macro_rules! set {
+        ($($field:ident),*) => {
+            $(if let Some(value) = add_args.$field {
+                ele.$field = value;
+            })*
+        };
+    }
+    set!(
+        tags,
+        name,
+        command,
+        process_type,
+        detection_start_cmd,
+        pid_search_cmd,
+        comment,
+        stop_signal,
+        stop_timeout,
+        reload_signal,
+        reload_cmd,
+        max_retries,
+        restart_backoff,
+        user,
+        group,
+        supplementary_groups,
+        umask,
+        depends_on
+    );
+    // 可以继承分组的字段，命令行中给出时覆盖分组的默认配置
+    if let Some(log_path) = add_args.log_path {
+        ele.log_path = Some(log_path);
+    }
+    if let Some(restart_policy) = add_args.restart_policy {
+        ele.restart_policy = Some(restart_policy);
+    }
+    if let Some(cwd) = add_args.cwd {
+        ele.cwd = Some(cwd);
+    }
+    ele.env.extend(add_args.env);
+    if let Some(env_files) = add_args.env_file {
+        ele.env_files = env_files;
+    }
+    if add_args.clear_env {
+        ele.clear_env = true;
+    }
+    if add_args.log_append {
+        ele.log_append = true;
+    }
+    if let Some(max_size) = add_args.log_max_size {
+        ele.log_rotation.max_size = max_size;
+    }
+    if let Some(max_age) = add_args.log_max_age {
+        ele.log_rotation.max_age = max_age;
+    }
+    if let Some(keep) = add_args.log_keep {
+        ele.log_rotation.keep = keep;
+    }
+    if let Some(nofile) = add_args.limit_nofile {
+        ele.limits.nofile = nofile;
+    }
+    if let Some(address_space) = add_args.limit_as {
+        ele.limits.address_space = address_space;
+    }
+    if let Some(core) = add_args.limit_core {
+        ele.limits.core = core;
+    }
+    if let Some(nice) = add_args.nice {
+        ele.limits.nice = Some(nice);
+    }
+    if let Some(oom_score_adj) = add_args.oom_score_adj {
+        ele.limits.oom_score_adj = Some(oom_score_adj);
+    }
+    if let Some(cpu_affinity) = add_args.cpu_affinity {
+        ele.limits.cpu_affinity = cpu_affinity;
+    }
+    if add_args.cgroup {
+        ele.cgroup.enabled = true;
+    }
+    if let Some(memory_max) = add_args.memory_max {
+        ele.cgroup.memory_max = memory_max;
+    }
+    if let Some(cpu_max) = add_args.cpu_max {
+        ele.cgroup.cpu_max = cpu_max;
+    }
+    if let Some(pids_max) = add_args.pids_max {
+        ele.cgroup.pids_max = pids_max;
+    }
+    if add_args.log_compress {
+        ele.log_rotation.compress = true;
+    }
+    ele.health = apply_health_args(
+        ele.health.take(),
+        add_args.health,
+        add_args.health_interval,
+        add_args.health_timeout,
+        add_args.health_retries,
+    );
+}
+
+/// 在 $EDITOR 中编辑 JSON5，校验失败时可以重新编辑，放弃修改时返回 None
+fn edit_in_editor(ele: &ProcessItem) -> Result<Option<ProcessItem>> {
+    let mut text = serde_json::to_string_pretty(ele)
+        .map_err(|e| Error::Invalid(format!("序列化配置失败: {}", e)))?;
+    loop {
+        let Some(edited) = Editor::new()
+            .extension(".json5")
+            .edit(&text)
+            .map_err(|e| Error::Invalid(format!("打开编辑器失败: {}", e)))?
+        else {
+            return Ok(None);
+        };
+        let result = json5::from_str::<ProcessItem>(&edited)
+            .map_err(|e| e.to_string())
+            .and_then(|it| it.validate().map(|_| it).map_err(|e| e.to_string()));
+        match result {
+            Ok(pi) => return Ok(Some(pi)),
+            Err(e) => {
+                eprintln!("配置有误: {}", e);
+                if !Confirm::with_theme(&ColorfulTheme::default())
+                    .with_prompt("重新编辑?")
+                    .default(true)
+                    .interact()
+                    .unwrap()
+                {
+                    return Ok(None);
+                }
+                text = edited;
+            }
+        }
     }
 }
@@ -0,0 +1,66 @@
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+
+/// 当前的配置版本，没有 `version` 字段的配置视为版本 0
+pub const CONFIG_VERSION: u32 = 2;
+
+/// 配置迁移，第 i 个函数把版本 i 的配置升级为版本 i + 1
+///
+/// 修改配置结构时在末尾追加迁移函数并增加 [`CONFIG_VERSION`]
+const MIGRATIONS: [fn(&mut Value) -> Result<()>; CONFIG_VERSION as usize] = [v0_to_v1, v1_to_v2];
+
+/// 版本 0：最初的配置，只有 `processes`
+fn v0_to_v1(_conf: &mut Value) -> Result<()> {
+    Ok(())
+}
+
+/// 版本 1：cwd、log_path 为空，restart_policy 为 never 时继承分组的配置
+///
+/// 版本 2 中省略这些字段表示继承，删除旧的默认值以保持原来的行为
+fn v1_to_v2(conf: &mut Value) -> Result<()> {
+    let Some(processes) = conf.get_mut("processes").and_then(Value::as_array_mut) else {
+        return Ok(());
+    };
+    for process in processes.iter_mut().filter_map(Value::as_object_mut) {
+        for (field, default) in [("cwd", ""), ("log_path", ""), ("restart_policy", "never")] {
+            if process.get(field).and_then(Value::as_str) == Some(default) {
+                process.remove(field);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 读取配置的版本
+pub fn version_of(conf: &Value) -> Result<u32> {
+    match conf.get("version") {
+        None | Some(Value::Null) => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|it| u32::try_from(it).ok())
+            .ok_or_else(|| Error::Invalid(format!("无效的配置版本：{}", version))),
+    }
+}
+
+/// 将配置升级到当前版本，返回升级前的版本，已是当前版本时返回 None
+pub fn migrate(conf: &mut Value) -> Result<Option<u32>> {
+    let version = version_of(conf)?;
+    if version > CONFIG_VERSION {
+        return Err(Error::Invalid(format!(
+            "配置版本 {} 高于当前支持的版本 {}，请升级 pm",
+            version, CONFIG_VERSION
+        )));
+    }
+    if version == CONFIG_VERSION {
+        return Ok(None);
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(conf)?;
+        if let Some(obj) = conf.as_object_mut() {
+            obj.insert("version".to_string(), Value::from(from as u32 + 1));
+        }
+    }
+    Ok(Some(version))
+}
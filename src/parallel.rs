@@ -0,0 +1,74 @@
+use std::{
+    sync::{Condvar, Mutex},
+    thread,
+};
+
+/// 默认并发数，即依次执行
+pub const DEFAULT_PARALLEL: usize = 1;
+
+/// 等待执行的任务
+struct Queue {
+    pending: Vec<usize>,
+    done: Vec<bool>,
+    running: usize,
+}
+
+/// 并发执行 `len` 个任务，同时最多执行 `limit` 个
+///
+/// 第 `i` 个任务在 `blockers[i]` 中的任务都完成后才开始，没有限制时按下标顺序开始。
+/// 结果按下标顺序返回，`limit` 为 1 时与依次执行相同
+pub fn run<T, F>(len: usize, limit: usize, blockers: &[Vec<usize>], work: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync,
+{
+    let queue = Mutex::new(Queue {
+        pending: (0..len).collect(),
+        done: vec![false; len],
+        running: 0,
+    });
+    let finished = Condvar::new();
+    let results = Mutex::new((0..len).map(|_| None).collect::<Vec<Option<T>>>());
+
+    thread::scope(|scope| {
+        for _ in 0..limit.clamp(1, len.max(1)) {
+            scope.spawn(|| loop {
+                let index = {
+                    let mut queue = queue.lock().unwrap();
+                    loop {
+                        if queue.pending.is_empty() {
+                            return;
+                        }
+                        let runnable = queue.pending.iter().position(|&i| {
+                            blockers
+                                .get(i)
+                                .is_none_or(|it| it.iter().all(|&b| queue.done[b]))
+                        });
+                        // 没有可以开始的任务也没有执行中的任务时说明存在循环，按顺序执行避免死锁
+                        match runnable.or((queue.running == 0).then_some(0)) {
+                            Some(position) => {
+                                queue.running += 1;
+                                break queue.pending.remove(position);
+                            }
+                            None => queue = finished.wait(queue).unwrap(),
+                        }
+                    }
+                };
+
+                let result = work(index);
+                results.lock().unwrap()[index] = Some(result);
+                let mut queue = queue.lock().unwrap();
+                queue.done[index] = true;
+                queue.running -= 1;
+                finished.notify_all();
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|it| it.expect("任务未执行"))
+        .collect()
+}
@@ -0,0 +1,99 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    os::fd::AsRawFd,
+    sync::atomic::{AtomicU32, Ordering},
+    time::SystemTime,
+};
+
+use crate::error::{Error, Result};
+
+/// 基于 flock 的排他锁，锁随文件关闭（包括进程退出）自动释放
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// 阻塞直到获得锁，锁文件不存在时创建
+    pub fn acquire(path: &str) -> Result<Self> {
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .map_err(|e| Error::io(path, e))?;
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+                return Ok(FileLock { _file: file });
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(Error::io(path, e));
+            }
+        }
+    }
+}
+
+/// 文件内容的指纹，用于判断读取后文件是否被修改
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileStamp {
+    mtime: Option<SystemTime>,
+    hash: u64,
+}
+
+impl FileStamp {
+    pub fn new(path: &str, data: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        FileStamp {
+            mtime: fs::metadata(path).and_then(|it| it.modified()).ok(),
+            hash: hasher.finish(),
+        }
+    }
+}
+
+/// 先写入同目录的临时文件并落盘，再重命名覆盖目标文件
+///
+/// 读取方只会看到完整的旧内容或新内容，写入中途崩溃也不会损坏原文件
+pub fn write_atomic(path: &str, data: &[u8]) -> Result<()> {
+    let tmp_path = tmp_path(path);
+    let result = File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result.map_err(|e| Error::io(path, e))
+}
+
+/// 与 [`write_atomic`] 相同，但目标文件已存在时不覆盖
+pub fn create_atomic(path: &str, data: &[u8]) -> Result<()> {
+    let tmp_path = tmp_path(path);
+    let result = File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|_| match fs::hard_link(&tmp_path, path) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+            result => result,
+        });
+    let _ = fs::remove_file(&tmp_path);
+    result.map_err(|e| Error::io(path, e))
+}
+
+/// 同目录下的临时文件名，同一进程的多个线程同时写入也不会冲突
+fn tmp_path(path: &str) -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    format!(
+        "{}.tmp.{}.{}",
+        path,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
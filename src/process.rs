@@ -1,15 +1,27 @@
 use std::{
-    ffi::OsString,
+    ffi::{CStr, CString, OsString},
     fs::File,
     io,
-    path::Path,
-    process::Command,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    os::{fd::OwnedFd, unix::process::CommandExt},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use chrono::DateTime;
 use duct::cmd;
-use sysinfo::System;
+use nix::{
+    sys::{signal::Signal, stat::Mode},
+    unistd::{Gid, Group, Pid, Uid, User},
+};
+use serde::{Deserialize, Serialize};
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System};
+
+use crate::{
+    error::{Error, Result},
+    limits::{self, ProcessLimits, ResourceLimits},
+    logs::{self, LogRotation, LogWriterArgs},
+};
 
 pub fn get_process_info(pid: u32) -> Option<(String, String, u64)> {
     let mut system = System::new_all();
@@ -19,8 +31,8 @@ pub fn get_process_info(pid: u32) -> Option<(String, String, u64)> {
         let cmd = process
             .cmd()
             .join(&OsString::from(" "))
-            .into_string()
-            .unwrap();
+            .to_string_lossy()
+            .into_owned();
 
         // 获取进程启动时间（单位: 秒，UNIX 时间戳）
         let start_time_seconds = process.start_time();
@@ -28,14 +40,12 @@ pub fn get_process_info(pid: u32) -> Option<(String, String, u64)> {
         // DateTime::from_timestamp
         // 格式化启动时间为 yyyy-MM-dd HH:mm:ss
         let formatted_time = DateTime::from_timestamp(start_time_seconds as i64, 0)
-            .unwrap()
-            .naive_local()
-            .format("%Y-%m-%d %H:%M:%S")
-            .to_string();
+            .map(|it| it.naive_local().format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
 
         // 计算执行时长
         let now = SystemTime::now();
-        let execution_duration = now.duration_since(start_time).unwrap().as_secs();
+        let execution_duration = now.duration_since(start_time).unwrap_or_default().as_secs();
 
         (cmd, formatted_time, execution_duration)
 
@@ -49,28 +59,625 @@ pub fn get_process_info(pid: u32) -> Option<(String, String, u64)> {
     // }
 }
 
-pub fn is_started(detection_start_cmd: &str) -> io::Result<bool> {
-    let result = cmd!("bash", "-c", detection_start_cmd).read()?;
+pub fn is_started(detection_start_cmd: &str) -> Result<bool> {
+    let result = cmd!("bash", "-c", detection_start_cmd)
+        .read()
+        .map_err(|e| Error::Detection {
+            command: detection_start_cmd.to_string(),
+            source: e,
+        })?;
 
     Ok(!result.is_empty())
 }
 
-pub fn swpan(command: &str, log_path: &str) {
-    cmd!("bash", "-c", command)
-        .before_spawn(|cmd| {
-            prevent_being_killed(cmd)?;
+/// 启动进程，返回子进程pid
+pub fn swpan(command: &str, log_path: &str) -> Result<u32> {
+    let options = SpawnOptions {
+        log_path: log_path.to_string(),
+        ..Default::default()
+    };
+    let child = spawn_detached(command, &options)?;
+    Ok(child.id())
+}
+
+/// 启动进程的参数
+#[derive(Clone, Debug, Default)]
+pub struct SpawnOptions {
+    /// 日志路径，为空时丢弃输出
+    pub log_path: String,
+    /// 追加写入日志，默认清空
+    pub log_append: bool,
+    pub log_rotation: LogRotation,
+    /// 工作目录，为空时继承 pm 的工作目录
+    pub cwd: String,
+    /// 不继承 pm 的环境变量
+    pub clear_env: bool,
+    /// 按顺序设置的环境变量
+    pub env: Vec<(String, String)>,
+    /// 切换到的用户和组，为 None 时与 pm 相同
+    pub credentials: Option<Credentials>,
+    /// 文件创建掩码，为 None 时继承 pm 的掩码
+    pub umask: Option<Mode>,
+    pub limits: ResourceLimits,
+    /// 子进程在 exec 之前加入的 cgroup 目录
+    pub cgroup: Option<String>,
+}
+
+/// 在子进程 exec 之前执行的设置，都在父进程中准备好
+#[derive(Default)]
+struct ChildSetup {
+    /// 要写入 `0` 的 `cgroup.procs` 路径
+    cgroup_procs: Option<CString>,
+    limits: Option<limits::PreparedLimits>,
+    credentials: Option<Credentials>,
+    umask: Option<Mode>,
+}
+
+/// 子进程运行的用户、组和附加组
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: Uid,
+    pub gid: Gid,
+    /// 附加组
+    pub groups: Vec<Gid>,
+}
+
+impl Credentials {
+    /// 查询用户和组，都为空时返回 None
+    ///
+    /// - 用户和组可以是名称或数字 id
+    /// - 没有指定组时使用用户的主组，也没有指定用户时使用 pm 的组
+    /// - 没有指定附加组时使用用户所属的全部组，只指定了组时清空附加组
+    pub fn lookup(user: &str, group: &str, groups: &[String]) -> Result<Option<Credentials>> {
+        if user.is_empty() && group.is_empty() && groups.is_empty() {
+            return Ok(None);
+        }
+
+        let user = if user.is_empty() {
+            None
+        } else {
+            Some(lookup_user(user)?)
+        };
+        let gid = match (group, &user) {
+            ("", Some(user)) => user.gid,
+            ("", None) => Gid::effective(),
+            (group, _) => lookup_group(group)?.gid,
+        };
+        let groups = match (groups, &user) {
+            ([], Some(user)) => {
+                let name = CString::new(user.name.as_str())
+                    .map_err(|_| Error::Invalid(format!("无效的用户名：{}", user.name)))?;
+                nix::unistd::getgrouplist(&name, gid).map_err(io::Error::from)?
+            }
+            ([], None) => vec![gid],
+            (groups, _) => groups
+                .iter()
+                .map(|it| lookup_group(it).map(|it| it.gid))
+                .collect::<Result<_>>()?,
+        };
+
+        Ok(Some(Credentials {
+            uid: user.map(|it| it.uid).unwrap_or_else(Uid::effective),
+            gid,
+            groups,
+        }))
+    }
+
+    /// 检查 pm 是否有权限切换，不需要切换时返回 None
+    fn check_privilege(self) -> Result<Option<Credentials>> {
+        if Uid::effective().is_root() {
+            return Ok(Some(self));
+        }
+
+        let mut current_groups = nix::unistd::getgroups().map_err(io::Error::from)?;
+        current_groups.sort_by_key(|it| it.as_raw());
+        current_groups.dedup();
+        let mut groups = self.groups.clone();
+        groups.sort_by_key(|it| it.as_raw());
+        groups.dedup();
+        if self.uid == Uid::effective() && self.gid == Gid::effective() && groups == current_groups
+        {
+            return Ok(None);
+        }
+        Err(Error::Permission(format!(
+            "pm 以 uid {} 运行，无法切换到 uid {} gid {}，需要以 root 运行 pm",
+            Uid::effective(),
+            self.uid,
+            self.gid
+        )))
+    }
+}
+
+/// 按名称或 uid 查询用户
+fn lookup_user(user: &str) -> Result<User> {
+    let found = match user.parse::<u32>() {
+        Ok(uid) => User::from_uid(Uid::from_raw(uid)),
+        Err(_) => User::from_name(user),
+    };
+    found
+        .map_err(io::Error::from)?
+        .ok_or_else(|| Error::Invalid(format!("用户不存在：{}", user)))
+}
+
+/// 按名称或 gid 查询组
+fn lookup_group(group: &str) -> Result<Group> {
+    let found = match group.parse::<u32>() {
+        Ok(gid) => Group::from_gid(Gid::from_raw(gid)),
+        Err(_) => Group::from_name(group),
+    };
+    found
+        .map_err(io::Error::from)?
+        .ok_or_else(|| Error::Invalid(format!("组不存在：{}", group)))
+}
+
+/// 解析八进制的文件创建掩码，如 `022`、`0027`，为空时返回 None
+pub fn parse_umask(umask: &str) -> Result<Option<Mode>> {
+    if umask.is_empty() {
+        return Ok(None);
+    }
+    match u32::from_str_radix(umask, 8) {
+        Ok(bits) if bits <= 0o777 => Ok(Some(Mode::from_bits_truncate(bits as libc::mode_t))),
+        _ => Err(Error::Invalid(format!("无效的 umask：{}", umask))),
+    }
+}
+
+/// 以脱离 pm 的方式启动 `bash -c command`
+///
+/// 子进程中新建会话（同时成为新的进程组组长），标准输入为 /dev/null，
+/// 标准输出和错误输出写入 `log_path`（为空时丢弃），并且不继承 pm 打开的文件描述符。
+/// 配置了日志轮转时输出经管道交给单独的日志写入进程
+pub fn spawn_detached(command: &str, options: &SpawnOptions) -> Result<Child> {
+    let log_path = options.log_path.as_str();
+    let append = options.log_append;
+    let rotation = &options.log_rotation;
+    if !options.cwd.is_empty() && !std::path::Path::new(&options.cwd).is_dir() {
+        return Err(Error::Invalid(format!("工作目录不存在：{}", options.cwd)));
+    }
+    let credentials = match options.credentials.clone() {
+        Some(credentials) => credentials.check_privilege()?,
+        None => None,
+    };
+    let cgroup_procs = match &options.cgroup {
+        Some(cgroup) => Some(
+            CString::new(format!("{}/cgroup.procs", cgroup))
+                .map_err(|_| Error::Invalid(format!("无效的 cgroup 路径：{}", cgroup)))?,
+        ),
+        None => None,
+    };
+    let setup = ChildSetup {
+        cgroup_procs,
+        limits: options.limits.prepare()?,
+        credentials,
+        umask: options.umask,
+    };
+
+    let stdout = if log_path.is_empty() {
+        File::options()
+            .write(true)
+            .open("/dev/null")
+            .map_err(|e| Error::io("/dev/null", e))?
+    } else if rotation.is_enabled() {
+        spawn_log_writer(log_path, append, rotation).map_err(|e| Error::io(log_path, e))?
+    } else {
+        File::options()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(log_path)
+            .map_err(|e| Error::io(log_path, e))?
+    };
+    let stderr = stdout.try_clone()?;
+
+    let mut cmd = Command::new("bash");
+    cmd.arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr);
+    if !options.cwd.is_empty() {
+        cmd.current_dir(&options.cwd);
+    }
+    if options.clear_env {
+        cmd.env_clear();
+    }
+    cmd.envs(options.env.iter().map(|(k, v)| (k, v)));
+    detach(&mut cmd, setup);
+
+    cmd.spawn().map_err(|e| Error::Spawn {
+        command: command.to_string(),
+        source: e,
+    })
+}
+
+/// 启动日志写入进程，返回连接到它标准输入的管道
+///
+/// 日志写入进程是 pm 自身（[`logs::LOG_WRITER_SUBCOMMAND`] 子命令），进程退出、管道关闭后它也会退出
+fn spawn_log_writer(log_path: &str, append: bool, rotation: &LogRotation) -> io::Result<File> {
+    let args = LogWriterArgs {
+        log_path: std::path::absolute(log_path)?
+            .to_string_lossy()
+            .into_owned(),
+        append,
+        rotation: rotation.clone(),
+    };
+    let args = json5::to_string(&args).map_err(io::Error::other)?;
+
+    let (reader, writer) = io::pipe()?;
+    let mut cmd = Command::new(std::env::current_exe()?);
+    cmd.arg(logs::LOG_WRITER_SUBCOMMAND)
+        .arg(args)
+        .current_dir("/")
+        .stdin(reader)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    detach(&mut cmd, ChildSetup::default());
+    let mut log_writer = cmd.spawn()?;
+
+    // 常驻的守护进程需要回收日志写入进程，CLI 退出后它由 init 接管
+    thread::spawn(move || log_writer.wait());
+
+    Ok(File::from(OwnedFd::from(writer)))
+}
+
+/// 子进程中新建会话并且不继承 pm 打开的文件描述符，之后加入 cgroup、应用资源限制、切换用户和文件创建掩码
+///
+/// cgroup、资源限制和组都要在切换用户之前设置，切换用户后就没有权限了
+fn detach(cmd: &mut Command, setup: ChildSetup) {
+    // SAFETY: pre_exec 中只调用异步信号安全的系统调用，需要的数据在父进程中已准备好
+    unsafe {
+        cmd.pre_exec(move || {
+            nix::unistd::setsid().map_err(io::Error::other)?;
+            mark_inherited_fds_cloexec();
+            if let Some(procs) = &setup.cgroup_procs {
+                write_in_child(procs, b"0")?;
+            }
+            if let Some(limits) = &setup.limits {
+                limits.apply()?;
+            }
+            if let Some(credentials) = &setup.credentials {
+                nix::unistd::setgroups(&credentials.groups)?;
+                nix::unistd::setgid(credentials.gid)?;
+                nix::unistd::setuid(credentials.uid)?;
+            }
+            if let Some(umask) = setup.umask {
+                nix::sys::stat::umask(umask);
+            }
             Ok(())
-        })
-        .stderr_to_stdout()
-        .stdout_path(log_path)
-        .start()
-        .unwrap()
-        .wait()
-        .unwrap();
+        });
+    }
+}
+
+/// 在 fork 后的子进程中写文件，不经过 std::fs 以免分配内存
+pub(crate) fn write_in_child(path: &CStr, data: &[u8]) -> io::Result<()> {
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let written = unsafe { libc::write(fd, data.as_ptr().cast(), data.len()) };
+    let result = if written < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    };
+    unsafe { libc::close(fd) };
+    result
 }
 
-fn prevent_being_killed(cmd: &Command) -> io::Result<()> {
-    nix::unistd::daemon(true, true)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+/// 将 3 及以上的文件描述符标记为 close-on-exec
+///
+/// 这里不能直接关闭，std 用一个 close-on-exec 的管道向父进程报告 exec 失败
+fn mark_inherited_fds_cloexec() {
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_close_range,
+            3 as libc::c_uint,
+            libc::c_uint::MAX,
+            libc::CLOSE_RANGE_CLOEXEC,
+        )
+    };
+    if ret == 0 {
+        return;
+    }
+
+    // 内核不支持 close_range 时逐个设置
+    let max_fd = match unsafe { libc::sysconf(libc::_SC_OPEN_MAX) } {
+        n if n > 0 => n.min(65536) as libc::c_int,
+        _ => 1024,
+    };
+    for fd in 3..max_fd {
+        unsafe {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
+}
+
+/// 执行 `pid_search_cmd` 查找不是由 pm 启动的进程，输出中的每个数字视为一个pid
+///
+/// 没有配置时返回空列表：只按命令行匹配会误伤其他配置、其他用户或 pm 之外启动的同名进程
+pub fn find_pids(pid_search_cmd: &str) -> Result<Vec<u32>> {
+    if pid_search_cmd.is_empty() {
+        return Ok(vec![]);
+    }
+    let output = cmd!("bash", "-c", pid_search_cmd)
+        .read()
+        .map_err(|e| Error::Detection {
+            command: pid_search_cmd.to_string(),
+            source: e,
+        })?;
+    Ok(output
+        .split_whitespace()
+        .filter_map(|it| it.parse::<u32>().ok())
+        .collect())
+}
+
+/// 解析信号名称，支持 `SIGTERM`、`TERM`、`15` 三种写法，空字符串视为 SIGTERM
+pub fn parse_signal(name: &str) -> Result<Signal> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Ok(Signal::SIGTERM);
+    }
+    if let Ok(num) = name.parse::<i32>() {
+        return Signal::try_from(num).map_err(|_| Error::Invalid(format!("未知信号: {}", name)));
+    }
+    let upper = name.to_uppercase();
+    let full = if upper.starts_with("SIG") {
+        upper
+    } else {
+        format!("SIG{}", upper)
+    };
+    full.parse::<Signal>()
+        .map_err(|_| Error::Invalid(format!("未知信号: {}", name)))
+}
+
+/// 执行重新加载命令，通过环境变量 `PM_PID` 传入正在运行的pid（多个时以空格分隔）
+pub fn run_reload_cmd(reload_cmd: &str, pids: &[u32]) -> Result<()> {
+    let pids = pids
+        .iter()
+        .map(|it| it.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    cmd!("bash", "-c", reload_cmd).env("PM_PID", pids).run()?;
+    Ok(())
+}
+
+/// 进程是否存活（僵尸进程视为已退出）
+pub fn is_alive(pid: u32) -> bool {
+    if nix::sys::signal::kill(Pid::from_raw(pid as i32), None).is_err() {
+        return false;
+    }
+
+    // /proc/<pid>/stat 第三列为进程状态，Z 为僵尸进程
+    match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => stat
+            .rsplit_once(')')
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .map(|state| state != "Z")
+            .unwrap_or(true),
+        Err(_) => true,
+    }
+}
+
+/// 发送信号给所有pid
+pub fn signal_all(pids: &[u32], signal: Signal) -> Result<()> {
+    for pid in pids {
+        match nix::sys::signal::kill(Pid::from_raw(*pid as i32), signal) {
+            // 进程已退出
+            Ok(_) | Err(nix::errno::Errno::ESRCH) => {}
+            Err(e) => return Err(Error::Os(e.into())),
+        }
+    }
     Ok(())
 }
+
+/// 进程树中的一个进程
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TreeProcess {
+    pub pid: u32,
+    pub ppid: u32,
+    /// 所在会话
+    pub sid: u32,
+    pub command: String,
+}
+
+/// 从 `/proc/<pid>/stat` 读取的父进程、会话和状态
+struct ProcStat {
+    pid: u32,
+    ppid: u32,
+    sid: u32,
+    zombie: bool,
+}
+
+fn read_proc_stat(pid: u32) -> Option<ProcStat> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // 第三列起依次为状态、ppid、pgrp、session
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace();
+    let state = fields.next()?;
+    let ppid = fields.next()?.parse().ok()?;
+    let sid = fields.nth(1)?.parse().ok()?;
+    Some(ProcStat {
+        pid,
+        ppid,
+        sid,
+        zombie: state == "Z",
+    })
+}
+
+/// 以 `roots` 为根的进程树中仍在运行的进程，按pid排序
+///
+/// `session` 为 true 时 `roots` 由 pm 用 setsid 启动，根进程是会话首进程或已退出时包括会话中的全部进程，
+/// 这样主进程退出后留下的子孙进程也能找到；之后沿父进程关系加入全部子孙进程。
+/// 会话首进程退出后，只要会话中还有进程，内核就不会复用它的pid。
+/// 非 pm 启动的进程（如通过pid查询命令找到的）所在的会话可能属于其他程序，只包括子孙进程
+pub fn process_tree(roots: &[u32], session: bool) -> Vec<TreeProcess> {
+    let own_pid = std::process::id();
+    let all = std::fs::read_dir("/proc")
+        .map(|it| {
+            it.filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+                .filter_map(read_proc_stat)
+                .filter(|it| !it.zombie && it.pid != own_pid)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let mut tree = vec![];
+    for root in roots {
+        let root_stat = all.iter().find(|it| it.pid == *root);
+        if session && root_stat.is_none_or(|it| it.sid == it.pid) {
+            tree.extend(all.iter().filter(|it| it.sid == *root).map(|it| it.pid));
+        }
+        if root_stat.is_some() {
+            tree.push(*root);
+        }
+    }
+    // 沿父进程关系加入子孙进程，直到没有新的进程
+    let mut idx = 0;
+    while idx < tree.len() {
+        let parent = tree[idx];
+        for child in all.iter().filter(|it| it.ppid == parent) {
+            if !tree.contains(&child.pid) {
+                tree.push(child.pid);
+            }
+        }
+        idx += 1;
+    }
+    tree.sort_unstable();
+    tree.dedup();
+
+    all.iter()
+        .filter(|it| tree.binary_search(&it.pid).is_ok())
+        .map(|it| TreeProcess {
+            pid: it.pid,
+            ppid: it.ppid,
+            sid: it.sid,
+            command: std::fs::read(format!("/proc/{}/cmdline", it.pid))
+                .map(|it| {
+                    String::from_utf8_lossy(&it)
+                        .trim_end_matches('\0')
+                        .replace('\0', " ")
+                })
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+fn tree_pids(roots: &[u32], session: bool) -> Vec<u32> {
+    process_tree(roots, session)
+        .into_iter()
+        .map(|it| it.pid)
+        .collect()
+}
+
+/// 结束以 `roots` 为根的整个进程树（见 [`process_tree`]）
+///
+/// 先发送 `signal`，等待 `grace` 后仍有进程则发送 SIGKILL，每次都重新收集进程树以包括新创建的子进程。
+/// 返回是否进行了强制结束
+pub fn terminate_tree(
+    roots: &[u32],
+    session: bool,
+    signal: Signal,
+    grace: Duration,
+) -> Result<bool> {
+    signal_all(&tree_pids(roots, session), signal)?;
+    if wait_tree_exit(roots, session, grace) {
+        return Ok(false);
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        signal_all(&tree_pids(roots, session), Signal::SIGKILL)?;
+        if wait_tree_exit(roots, session, Duration::from_millis(500)) {
+            return Ok(true);
+        }
+        if Instant::now() >= deadline {
+            return Err(Error::Os(io::Error::other("发送 SIGKILL 后进程仍未退出")));
+        }
+    }
+}
+
+fn wait_tree_exit(roots: &[u32], session: bool, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if tree_pids(roots, session).is_empty() {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// 读取 `/proc/<pid>/stat` 中的进程启动时间（开机后的时钟周期数）
+///
+/// 与pid一起记录，用于识别pid被复用的情况
+pub fn proc_start_ticks(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // 进程名可能包含空格和括号，从最后一个 ')' 之后开始解析，starttime 为第 22 列
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_whitespace().nth(19)?.parse().ok()
+}
+
+/// 进程运行指标
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProcessStats {
+    pub pid: u32,
+    /// 运行时长（秒）
+    pub run_time: u64,
+    /// CPU 使用率（百分比，多核可超过 100）
+    pub cpu_usage: f32,
+    /// 常驻内存（字节）
+    pub memory: u64,
+    pub threads: usize,
+    /// 实际的工作目录
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// 实际生效的资源限制
+    #[serde(default)]
+    pub limits: Option<ProcessLimits>,
+}
+
+/// 采集进程的运行指标，已退出的pid会被忽略
+///
+/// CPU 使用率需要两次采样，调用会阻塞 `sysinfo::MINIMUM_CPU_UPDATE_INTERVAL`
+pub fn get_process_stats(pids: &[u32]) -> Vec<ProcessStats> {
+    if pids.is_empty() {
+        return vec![];
+    }
+
+    let sys_pids = pids
+        .iter()
+        .map(|it| sysinfo::Pid::from_u32(*it))
+        .collect::<Vec<_>>();
+    let refresh_kind = ProcessRefreshKind::new()
+        .with_cpu()
+        .with_memory()
+        .with_cwd(sysinfo::UpdateKind::OnlyIfNotSet);
+    let mut system = System::new();
+    system.refresh_processes_specifics(ProcessesToUpdate::Some(&sys_pids), true, refresh_kind);
+    thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    system.refresh_processes_specifics(ProcessesToUpdate::Some(&sys_pids), true, refresh_kind);
+
+    sys_pids
+        .iter()
+        .filter_map(|pid| system.process(*pid))
+        .map(|process| ProcessStats {
+            pid: process.pid().as_u32(),
+            run_time: process.run_time(),
+            cpu_usage: process.cpu_usage(),
+            memory: process.memory(),
+            threads: thread_count(process.pid().as_u32()).unwrap_or(1),
+            cwd: process.cwd().map(|it| it.to_string_lossy().into_owned()),
+            limits: limits::read_process_limits(process.pid().as_u32()),
+        })
+        .collect()
+}
+
+/// 读取 `/proc/<pid>/status` 中的线程数
+fn thread_count(pid: u32) -> Option<usize> {
+    std::fs::read_to_string(format!("/proc/{}/status", pid))
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("Threads:"))
+        .and_then(|it| it.trim().parse().ok())
+}
@@ -0,0 +1,126 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{Error, Result};
+
+/// 未选择配置时使用的配置名，对应 pm 主目录本身
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// pm 主目录下的配置管理
+///
+/// 主目录依次取 `PM_HOME`、`$XDG_CONFIG_HOME/process_manager`、`~/.process_manager`。
+/// 默认配置就是主目录本身，命名配置位于 `profiles/<name>`，当前选择的配置名记录在 `current` 文件中
+pub struct Profiles {
+    home: PathBuf,
+}
+
+impl Profiles {
+    pub fn new(home: impl Into<PathBuf>) -> Self {
+        Profiles { home: home.into() }
+    }
+
+    /// 根据环境变量确定主目录
+    pub fn from_env() -> Result<Self> {
+        if let Some(home) = env::var_os("PM_HOME").filter(|it| !it.is_empty()) {
+            return Ok(Profiles::new(home));
+        }
+        if let Some(config) = env::var_os("XDG_CONFIG_HOME").filter(|it| !it.is_empty()) {
+            return Ok(Profiles::new(Path::new(&config).join("process_manager")));
+        }
+        match env::var_os("HOME").filter(|it| !it.is_empty()) {
+            Some(home) => Ok(Profiles::new(Path::new(&home).join(".process_manager"))),
+            None => Err(Error::Invalid(
+                "无法确定主目录，请设置 PM_HOME 或使用 --profile".to_string(),
+            )),
+        }
+    }
+
+    pub fn home(&self) -> &Path {
+        &self.home
+    }
+
+    /// 确定本次使用的配置目录并确保它存在，`--profile` 指定的目录优先于当前选择的配置
+    pub fn resolve(profile_dir: Option<&str>) -> Result<String> {
+        let dir = match profile_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let profiles = Profiles::from_env()?;
+                profiles.dir(&profiles.current())?
+            }
+        };
+        fs::create_dir_all(&dir).map_err(|e| Error::io(dir.to_string_lossy(), e))?;
+        Ok(dir.to_string_lossy().into_owned())
+    }
+
+    /// 当前选择的配置名
+    pub fn current(&self) -> String {
+        fs::read_to_string(self.home.join("current"))
+            .map(|it| it.trim().to_string())
+            .ok()
+            .filter(|it| !it.is_empty())
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+    }
+
+    /// 所有配置名，默认配置排在最前
+    pub fn list(&self) -> Result<Vec<String>> {
+        let profiles_dir = self.home.join("profiles");
+        let mut names = vec![];
+        let read = fs::read_dir(&profiles_dir).and_then(|entries| {
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    names.push(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+            Ok(())
+        });
+        match read {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(Error::io(profiles_dir.to_string_lossy(), e))
+            }
+            _ => {}
+        }
+        names.sort();
+        names.insert(0, DEFAULT_PROFILE.to_string());
+        Ok(names)
+    }
+
+    /// 切换当前配置，配置不存在时创建
+    pub fn switch(&self, name: &str) -> Result<String> {
+        let dir = self.dir(name)?;
+        fs::create_dir_all(&dir).map_err(|e| Error::io(dir.to_string_lossy(), e))?;
+        let current = self.home.join("current");
+        let written = if name == DEFAULT_PROFILE {
+            match fs::remove_file(&current) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            }
+        } else {
+            fs::write(&current, name)
+        };
+        written.map_err(|e| Error::io(current.to_string_lossy(), e))?;
+        Ok(dir.to_string_lossy().into_owned())
+    }
+
+    /// 配置名对应的目录
+    pub fn dir(&self, name: &str) -> Result<PathBuf> {
+        if name == DEFAULT_PROFILE {
+            return Ok(self.home.clone());
+        }
+        let valid = !name.is_empty()
+            && name != "."
+            && name != ".."
+            && name
+                .chars()
+                .all(|it| it.is_alphanumeric() || matches!(it, '-' | '_' | '.'));
+        if !valid {
+            return Err(Error::Invalid(format!(
+                "无效的配置名：{}，只能包含字母、数字、-、_、.",
+                name
+            )));
+        }
+        Ok(self.home.join("profiles").join(name))
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io::Read,
+    path::Path,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cgroup::Cgroup,
+    error::{Error, Result},
+    persist::{self, FileLock},
+    process,
+};
+
+/// 一次启动的运行记录
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RunRecord {
+    pub pid: u32,
+    /// 本次启动的唯一标识
+    pub run_id: String,
+    /// 实际执行的命令
+    pub command: String,
+    /// 启动时间（UNIX 时间戳，秒）
+    pub started_at: u64,
+    /// 启动时间（UNIX 时间戳，毫秒），用于计算启动到就绪的时长
+    #[serde(default)]
+    pub started_at_ms: Option<u64>,
+    /// `/proc/<pid>/stat` 中的启动时间，用于识别pid复用
+    pub proc_start_ticks: Option<u64>,
+    /// 进程所在的 cgroup 目录
+    #[serde(default)]
+    pub cgroup: Option<String>,
+    /// 配置了 cgroup 但无法使用或无法写入限制的原因
+    #[serde(default)]
+    pub cgroup_warning: Option<String>,
+}
+
+/// 生成新的运行标识，在启动前生成以便展开 `{run_id}`
+pub fn new_run_id() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    format!(
+        "{:x}-{:x}{:x}",
+        millis,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+impl RunRecord {
+    pub fn new(pid: u32, command: &str) -> Self {
+        RunRecord::with_run_id(new_run_id(), pid, command)
+    }
+
+    pub fn with_run_id(run_id: String, pid: u32, command: &str) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        RunRecord {
+            pid,
+            run_id,
+            command: command.to_string(),
+            started_at: now.as_secs(),
+            started_at_ms: Some(now.as_millis() as u64),
+            proc_start_ticks: process::proc_start_ticks(pid),
+            cgroup: None,
+            cgroup_warning: None,
+        }
+    }
+
+    /// 从启动到现在的时长，旧版本的记录只精确到秒
+    pub fn elapsed(&self) -> Duration {
+        let started_at = Duration::from_millis(
+            self.started_at_ms
+                .unwrap_or_else(|| self.started_at.saturating_mul(1000)),
+        );
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .saturating_sub(started_at)
+    }
+
+    /// 记录的进程是否仍在运行
+    ///
+    /// 主进程退出后，cgroup 或启动时创建的会话中还有进程时仍视为运行中
+    pub fn is_alive(&self) -> bool {
+        if let Some(pids) = self
+            .cgroup
+            .as_deref()
+            .and_then(|it| Cgroup::open(it).pids().ok())
+        {
+            return pids.iter().any(|pid| process::is_alive(*pid));
+        }
+        if !process::is_alive(self.pid) {
+            return !process::process_tree(&[self.pid], true).is_empty();
+        }
+        match self.proc_start_ticks {
+            Some(ticks) => process::proc_start_ticks(self.pid) == Some(ticks),
+            None => true,
+        }
+    }
+}
+
+/// 最近一次退出的记录
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExitRecord {
+    pub pid: u32,
+    pub run_id: String,
+    /// 退出码，进程不是由 pm 回收时无法得知
+    pub exit_code: Option<i32>,
+    /// 退出（或被发现已退出）的时间（UNIX 时间戳，秒）
+    pub exited_at: u64,
+    /// 通过 `pm stop` 主动停止
+    #[serde(default)]
+    pub stopped: bool,
+    /// 守护进程已放弃重启
+    #[serde(default)]
+    pub crash_loop: bool,
+    /// 健康检查连续失败，被守护进程结束
+    #[serde(default)]
+    pub unhealthy: bool,
+}
+
+impl ExitRecord {
+    fn new(record: &RunRecord, exit_code: Option<i32>) -> Self {
+        ExitRecord {
+            pid: record.pid,
+            run_id: record.run_id.clone(),
+            exit_code,
+            exited_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            stopped: false,
+            crash_loop: false,
+            unhealthy: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct State {
+    /// 唯一程序名 -> 运行记录
+    runs: BTreeMap<String, RunRecord>,
+    /// 唯一程序名 -> 最近一次退出记录
+    #[serde(default)]
+    exits: BTreeMap<String, ExitRecord>,
+}
+
+impl State {
+    /// 将运行记录转为退出记录，没有运行记录时返回 None
+    fn exit(&mut self, name: &str, exit_code: Option<i32>) -> Option<&mut ExitRecord> {
+        let record = self.runs.remove(name)?;
+        self.exits
+            .insert(name.to_string(), ExitRecord::new(&record, exit_code));
+        self.exits.get_mut(name)
+    }
+}
+
+/// 保存在配置文件旁的进程运行状态（`.state.json`）
+///
+/// CLI、守护进程和同一进程的多个线程都可能同时修改运行状态，每次修改都持有 `.state.lock`，
+/// 在磁盘上最新的状态上执行修改后再保存，不会覆盖其他人写入的记录
+pub struct StateStore {
+    state_path: String,
+    /// 读取和修改运行状态时持有的锁文件
+    lock_path: String,
+    state: State,
+    /// 本次加载时发现已不存在的进程
+    stale: Vec<(String, RunRecord)>,
+}
+
+impl StateStore {
+    pub fn load(profile_path: &str) -> Result<Self> {
+        let state_path = format!("{}/.state.json", profile_path);
+        let lock_path = format!("{}/.state.lock", profile_path);
+
+        let _lock = FileLock::acquire(&lock_path)?;
+        let mut store = StateStore {
+            state: StateStore::read(&state_path)?,
+            state_path,
+            lock_path,
+            stale: vec![],
+        };
+        store.reconcile()?;
+        Ok(store)
+    }
+
+    fn read(state_path: &str) -> Result<State> {
+        if !Path::new(state_path).exists() {
+            return Ok(State::default());
+        }
+        let mut data = String::new();
+        OpenOptions::new()
+            .read(true)
+            .open(state_path)
+            .and_then(|mut file| file.read_to_string(&mut data))
+            .map_err(|e| Error::io(state_path, e))?;
+        json5::from_str(&data).map_err(|e| Error::config_parse(state_path, e))
+    }
+
+    /// 对照 `/proc` 清理已退出（崩溃、重启）的记录，调用方需持有锁
+    fn reconcile(&mut self) -> Result<()> {
+        let (alive, stale): (BTreeMap<_, _>, BTreeMap<_, _>) = std::mem::take(&mut self.state.runs)
+            .into_iter()
+            .partition(|(_, record)| record.is_alive());
+        self.state.runs = alive;
+        for (name, record) in &stale {
+            self.state
+                .exits
+                .insert(name.clone(), ExitRecord::new(record, None));
+            // 进程都已退出，删除留下的空 cgroup
+            if let Some(cgroup) = &record.cgroup {
+                Cgroup::open(cgroup).remove();
+            }
+        }
+        self.stale = stale.into_iter().collect();
+
+        if !self.stale.is_empty() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// 持锁重新读取后执行修改，`f` 返回 true 时保存
+    fn modify<F>(&mut self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut State) -> bool,
+    {
+        let _lock = FileLock::acquire(&self.lock_path)?;
+        self.state = StateStore::read(&self.state_path)?;
+        if f(&mut self.state) {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// 本次加载时被清理的过期记录
+    pub fn stale(&self) -> &[(String, RunRecord)] {
+        &self.stale
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RunRecord> {
+        self.state.runs.get(name)
+    }
+
+    pub fn record(&mut self, name: &str, record: RunRecord) -> Result<()> {
+        self.modify(|state| {
+            state.runs.insert(name.to_string(), record);
+            true
+        })
+    }
+
+    pub fn last_exit(&self, name: &str) -> Option<&ExitRecord> {
+        self.state.exits.get(name)
+    }
+
+    /// 进程已退出，将运行记录转为退出记录
+    pub fn record_exit(&mut self, name: &str, exit_code: Option<i32>) -> Result<()> {
+        self.modify(|state| state.exit(name, exit_code).is_some())
+    }
+
+    /// 进程被 `pm stop` 主动停止，守护进程不会再重启它
+    pub fn record_stop(&mut self, name: &str) -> Result<()> {
+        self.modify(|state| match state.exit(name, None) {
+            Some(exit) => {
+                exit.stopped = true;
+                true
+            }
+            None => match state.exits.get_mut(name) {
+                Some(exit) => {
+                    exit.stopped = true;
+                    exit.crash_loop = false;
+                    true
+                }
+                None => false,
+            },
+        })
+    }
+
+    /// 进程因健康检查失败被守护进程结束，退出码由回收时补充
+    pub fn record_unhealthy(&mut self, name: &str) -> Result<()> {
+        self.modify(|state| match state.exit(name, None) {
+            Some(exit) => {
+                exit.unhealthy = true;
+                true
+            }
+            None => false,
+        })
+    }
+
+    /// 守护进程回收到子进程后补充退出码
+    ///
+    /// 加载状态时对照 `/proc` 可能已经把该次运行转为退出记录（此时退出码未知），
+    /// 也可能被 `pm stop` 标记为主动停止，这两种情况下只补充退出码
+    pub fn record_reaped(
+        &mut self,
+        name: &str,
+        run_id: &str,
+        exit_code: Option<i32>,
+    ) -> Result<Option<ExitRecord>> {
+        let mut reaped = false;
+        self.modify(|state| {
+            if state.runs.get(name).is_some_and(|it| it.run_id == run_id) {
+                state.exit(name, exit_code);
+            } else if let Some(exit) = state.exits.get_mut(name).filter(|it| it.run_id == run_id) {
+                exit.exit_code = exit_code;
+            } else {
+                return false;
+            }
+            reaped = true;
+            true
+        })?;
+        Ok(self.state.exits.get(name).filter(|_| reaped).cloned())
+    }
+
+    /// 进程改名，运行记录和退出记录跟随新名称
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        self.modify(|state| {
+            let run = state.runs.remove(from);
+            let exit = state.exits.remove(from);
+            if run.is_none() && exit.is_none() {
+                return false;
+            }
+            if let Some(run) = run {
+                state.runs.insert(to.to_string(), run);
+            }
+            if let Some(exit) = exit {
+                state.exits.insert(to.to_string(), exit);
+            }
+            true
+        })
+    }
+
+    /// 标记进程进入崩溃循环
+    pub fn mark_crash_loop(&mut self, name: &str) -> Result<()> {
+        self.modify(|state| match state.exits.get_mut(name) {
+            Some(exit) => {
+                exit.crash_loop = true;
+                true
+            }
+            None => false,
+        })
+    }
+
+    /// 调用方需持有锁
+    fn save(&self) -> Result<()> {
+        let serialized = json5::to_string(&self.state)
+            .map_err(|e| Error::Invalid(format!("序列化运行状态失败: {}", e)))?;
+        persist::write_atomic(&self.state_path, serialized.as_bytes())
+    }
+}
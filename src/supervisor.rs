@@ -0,0 +1,483 @@
+use std::{
+    collections::HashMap,
+    os::unix::process::ExitStatusExt,
+    process::Child,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use nix::sys::signal::Signal;
+
+use crate::{
+    cgroup::Cgroup,
+    deps,
+    error::Result,
+    group,
+    health::HealthStatus,
+    ipc::{Action, Request, Responder, Response, ResponseBody, Server},
+    ProcessItem, ProcessManager, RestartPolicy, StartStatus, StopStatus,
+};
+
+/// 检查间隔
+const TICK: Duration = Duration::from_millis(500);
+/// 处理控制请求的间隔
+const POLL: Duration = Duration::from_millis(50);
+/// 运行超过该时长视为稳定运行，重置重启计数
+const STABLE_RUN_TIME: Duration = Duration::from_secs(60);
+/// 重启等待时长上限
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// 守护进程自己启动的子进程
+struct OwnedChild {
+    child: Child,
+    run_id: String,
+}
+
+/// 后台线程启动的进程，由主循环持有子进程
+struct Started {
+    name: String,
+    status: StartStatus,
+    child: Option<Child>,
+    run_id: String,
+}
+
+/// 后台线程发给主循环的消息
+enum Background {
+    Started(Started),
+    /// 请求处理完成，检查时不再跳过这些进程
+    Done(Vec<String>),
+}
+
+/// 单个进程的重启状态
+#[derive(Default)]
+struct Supervised {
+    /// 连续重启次数
+    retries: u32,
+    /// 计划的重启时间
+    restart_at: Option<Instant>,
+    /// 已经接管的最近一次运行
+    seen_run_id: Option<String>,
+    /// 最近一次运行开始的时间
+    running_since: Option<Instant>,
+    /// 下次健康检查的时间
+    next_health_at: Option<Instant>,
+    /// 健康检查连续失败次数
+    health_failures: u32,
+    /// 正在等待依赖就绪
+    waiting_dependencies: bool,
+}
+
+/// 常驻的守护进程，回收自己启动的子进程并按照重启策略重启
+///
+/// 每次检查都重新读取配置和运行状态，所以修改配置、`pm start`、`pm stop` 无需重启守护进程。
+/// 通过 `pm stop` 停止的进程不会被重启，通过 `pm start` 启动的进程会被接管
+pub struct Supervisor {
+    profile_path: String,
+    children: HashMap<String, OwnedChild>,
+    supervised: HashMap<String, Supervised>,
+    /// 后台线程正在启动、停止或重启的进程及请求数，检查时跳过，避免重复启动或重启正在停止的进程
+    busy: HashMap<String, usize>,
+    background_tx: Sender<Background>,
+    background_rx: Receiver<Background>,
+}
+
+impl Supervisor {
+    pub fn new(profile_path: &str) -> Self {
+        let (background_tx, background_rx) = mpsc::channel();
+        Supervisor {
+            profile_path: profile_path.to_string(),
+            children: HashMap::new(),
+            supervised: HashMap::new(),
+            busy: HashMap::new(),
+            background_tx,
+            background_rx,
+        }
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        let server = Server::bind(&self.profile_path)?;
+        log(&format!("守护进程已启动，配置目录：{}", self.profile_path));
+
+        let mut next_tick = Instant::now();
+        loop {
+            if Instant::now() >= next_tick {
+                // 配置文件编辑到一半等错误不会结束守护进程，下次检查时重试
+                if let Err(e) = self.tick() {
+                    log(&format!("检查失败: {}", e));
+                }
+                next_tick = Instant::now() + TICK;
+            }
+            server.handle_pending(|request, responder| self.handle(request, responder))?;
+            thread::sleep(POLL);
+        }
+    }
+
+    /// 处理 CLI 发来的请求，启动的进程由守护进程持有
+    ///
+    /// 除列出配置外，请求可能要等待依赖就绪、进程退出或执行命令，都在后台线程中处理并回复，
+    /// 期间照常回收、重启和处理其他请求
+    fn handle(&mut self, request: Request, responder: Responder) {
+        let mut pm = match ProcessManager::new(&self.profile_path) {
+            Ok(pm) => pm,
+            Err(e) => return responder.send(Response::new(ResponseBody::Error(e.to_string()))),
+        };
+        let names = request.names;
+        pm.set_parallel(request.parallel);
+
+        let action = request.action;
+        let selected = pm
+            .conf
+            .processes
+            .iter()
+            .filter(|it| names.contains(&it.name));
+        let busy = match action {
+            Action::List => {
+                let items = selected.cloned().collect();
+                return responder.send(Response::new(ResponseBody::List(items)));
+            }
+            Action::Start => deps::start_order(&pm.conf.processes, &names),
+            Action::Stop | Action::Restart => selected.map(|it| it.name.clone()).collect(),
+            Action::Reload | Action::Status | Action::Logs => vec![],
+        };
+        self.reserve(&busy);
+
+        let background_tx = self.background_tx.clone();
+        thread::spawn(move || {
+            let done = Done {
+                names: busy,
+                background_tx: background_tx.clone(),
+            };
+            let body = match action {
+                Action::Start => ResponseBody::Start(
+                    pm.spawn_all(&names)
+                        .into_iter()
+                        .map(|(name, status, child)| {
+                            hand_over(&background_tx, &pm, &name, &status, child);
+                            (name, status)
+                        })
+                        .collect(),
+                ),
+                Action::Restart => ResponseBody::Restart(
+                    pm.respawn_all(&names)
+                        .into_iter()
+                        .map(|(name, stop_status, start_status, child)| {
+                            hand_over(&background_tx, &pm, &name, &start_status, child);
+                            (name, stop_status, start_status)
+                        })
+                        .collect(),
+                ),
+                Action::Stop => ResponseBody::Stop(pm.stop(names)),
+                Action::Reload => ResponseBody::Reload(pm.reload(names)),
+                Action::Status => ResponseBody::Status(pm.status(names)),
+                Action::Logs => ResponseBody::Logs(pm.logs(names)),
+                Action::List => unreachable!(),
+            };
+            // 先让主循环不再跳过这些进程，再回复
+            drop(done);
+            responder.send(Response::new(body));
+        });
+    }
+
+    /// 后台线程开始处理这些进程
+    fn reserve(&mut self, names: &[String]) {
+        for name in names {
+            *self.busy.entry(name.clone()).or_default() += 1;
+        }
+    }
+
+    /// 后台线程处理完这些进程，同一进程的请求都处理完后检查时不再跳过
+    fn release(&mut self, names: &[String]) {
+        for name in names {
+            if let Some(count) = self.busy.get_mut(name) {
+                *count -= 1;
+                if *count == 0 {
+                    self.busy.remove(name);
+                }
+            }
+        }
+    }
+
+    /// 持有后台线程启动的子进程
+    fn collect_background(&mut self) {
+        while let Ok(message) = self.background_rx.try_recv() {
+            match message {
+                Background::Started(started) => self.adopt(
+                    &started.name,
+                    &started.status,
+                    started.child,
+                    started.run_id,
+                ),
+                Background::Done(names) => self.release(&names),
+            }
+        }
+    }
+
+    /// 持有手动启动的子进程
+    fn adopt(&mut self, name: &str, status: &StartStatus, child: Option<Child>, run_id: String) {
+        if let (StartStatus::Started(pid), Some(child)) = (status, child) {
+            log(&format!("{} 已启动(pid:{})", name, pid));
+            let sup = self.supervised.entry(name.to_string()).or_default();
+            sup.retries = 0;
+            sup.restart_at = None;
+            sup.seen_run_id = Some(run_id.clone());
+            sup.running_since = Some(Instant::now());
+            sup.next_health_at = None;
+            sup.health_failures = 0;
+            self.children
+                .insert(name.to_string(), OwnedChild { child, run_id });
+        }
+    }
+
+    pub(crate) fn tick(&mut self) -> Result<()> {
+        self.collect_background();
+        let mut pm = ProcessManager::new(&self.profile_path)?;
+        self.reap(&mut pm)?;
+
+        let ProcessManager {
+            pm_path,
+            conf,
+            state,
+            ..
+        } = &mut pm;
+        for ele in &conf.processes {
+            if self.busy.contains_key(&ele.name) {
+                continue;
+            }
+            let ele = &group::effective(&conf.groups, ele);
+            let now = Instant::now();
+            let sup = self.supervised.entry(ele.name.clone()).or_default();
+
+            if let Some(record) = state.get(&ele.name) {
+                if sup.seen_run_id.as_deref() != Some(record.run_id.as_str()) {
+                    if !self.children.contains_key(&ele.name) {
+                        // 通过 pm start 手动启动，重新计算重启次数
+                        log(&format!(
+                            "{} 接管运行中的进程(pid:{})",
+                            ele.name, record.pid
+                        ));
+                        sup.retries = 0;
+                    }
+                    sup.seen_run_id = Some(record.run_id.clone());
+                    sup.running_since = Some(now);
+                    sup.restart_at = None;
+                    sup.next_health_at = None;
+                    sup.health_failures = 0;
+                }
+                if sup
+                    .running_since
+                    .is_some_and(|it| now.duration_since(it) >= STABLE_RUN_TIME)
+                {
+                    sup.retries = 0;
+                }
+
+                let Some(health) = &ele.health else {
+                    continue;
+                };
+                if sup.next_health_at.is_some_and(|it| now < it) {
+                    continue;
+                }
+                sup.next_health_at = Some(now + health.interval());
+                match ProcessManager::probe_health(pm_path, ele, Some(record)) {
+                    Some(HealthStatus::Unhealthy(reason)) => {
+                        sup.health_failures += 1;
+                        log(&format!(
+                            "{} 健康检查失败({}/{}): {}",
+                            ele.name,
+                            sup.health_failures,
+                            health.retries(),
+                            reason
+                        ));
+                    }
+                    _ => {
+                        if sup.health_failures >= health.retries() {
+                            log(&format!("{} 恢复健康", ele.name));
+                        }
+                        sup.health_failures = 0;
+                    }
+                }
+                if sup.health_failures < health.retries() {
+                    continue;
+                }
+                // 不重启时只在刚达到失败次数时提示一次，之后每次失败只记录日志
+                if ele.restart_policy() == RestartPolicy::Never {
+                    if sup.health_failures == health.retries() {
+                        log(&format!("{} 不健康，重启策略为 never，不重启", ele.name));
+                    }
+                    continue;
+                }
+                log(&format!("{} 不健康，结束后重启", ele.name));
+                // 结束失败时进程仍在运行，不能按退出处理，下次检查失败时再尝试结束
+                if let StopStatus::Failed(e) = ProcessManager::terminate_one(state, ele) {
+                    log(&format!("{} 结束失败: {}", ele.name, e));
+                    continue;
+                }
+                state.record_unhealthy(&ele.name)?;
+                sup.next_health_at = None;
+                sup.health_failures = 0;
+            }
+
+            if !should_run(ele, state.last_exit(&ele.name)) {
+                sup.restart_at = None;
+                continue;
+            }
+
+            let restart_at = match sup.restart_at {
+                Some(restart_at) => restart_at,
+                None => {
+                    if sup.retries >= ele.max_retries {
+                        log(&format!(
+                            "{} 连续重启 {} 次失败，进入崩溃循环状态，不再重启",
+                            ele.name, sup.retries
+                        ));
+                        state.mark_crash_loop(&ele.name)?;
+                        continue;
+                    }
+
+                    let delay = if state.last_exit(&ele.name).is_none() {
+                        Duration::ZERO
+                    } else {
+                        backoff(ele.restart_backoff, sup.retries)
+                    };
+                    if !delay.is_zero() {
+                        log(&format!("{} 将在 {}s 后重启", ele.name, delay.as_secs()));
+                    }
+                    *sup.restart_at.insert(now + delay)
+                }
+            };
+            if now < restart_at {
+                continue;
+            }
+            // 等待依赖的进程就绪后再启动，不计入重启次数
+            if !ProcessManager::dependencies_ready(pm_path, state, conf, ele) {
+                if !sup.waiting_dependencies {
+                    log(&format!("{} 等待依赖就绪", ele.name));
+                    sup.waiting_dependencies = true;
+                }
+                continue;
+            }
+            sup.waiting_dependencies = false;
+
+            sup.restart_at = None;
+            sup.retries += 1;
+            match ProcessManager::spawn_one(state, pm_path, ele) {
+                (StartStatus::Started(pid), Some(child)) => {
+                    log(&format!("{} 已启动(pid:{})", ele.name, pid));
+                    let run_id = state
+                        .get(&ele.name)
+                        .map(|it| it.run_id.clone())
+                        .unwrap_or_default();
+                    sup.seen_run_id = Some(run_id.clone());
+                    sup.running_since = Some(now);
+                    self.children
+                        .insert(ele.name.clone(), OwnedChild { child, run_id });
+                }
+                (status, _) => log(&format!("{} {}", ele.name, status)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 回收已退出的子进程并记录退出码
+    fn reap(&mut self, pm: &mut ProcessManager) -> Result<()> {
+        let mut exited = vec![];
+        for (name, owned) in self.children.iter_mut() {
+            if let Some(status) = owned.child.try_wait()? {
+                // 被信号结束时按 shell 的习惯记为 128 + 信号值
+                let code = status.code().or_else(|| status.signal().map(|it| 128 + it));
+                exited.push((name.clone(), owned.run_id.clone(), code));
+            }
+        }
+
+        for (name, run_id, code) in exited {
+            self.children.remove(&name);
+            // 主进程退出后结束 cgroup 中残留的子孙进程
+            if let Some(cgroup) = pm
+                .state
+                .get(&name)
+                .filter(|it| it.run_id == run_id)
+                .and_then(|it| it.cgroup.as_deref())
+                .map(Cgroup::open)
+                .filter(|it| it.exists())
+            {
+                if let Err(e) = cgroup.terminate(Signal::SIGKILL, Duration::ZERO) {
+                    log(&format!("{} 清理 cgroup 失败: {}", name, e));
+                }
+            }
+            let exit = pm.state.record_reaped(&name, &run_id, code)?;
+            let code = code
+                .map(|it| it.to_string())
+                .unwrap_or_else(|| "未知".to_string());
+            match exit {
+                Some(exit) if exit.stopped => log(&format!("{} 已停止，退出码 {}", name, code)),
+                _ => log(&format!("{} 已退出，退出码 {}", name, code)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// 根据重启策略和最近一次退出判断是否需要（重新）启动
+fn should_run(ele: &ProcessItem, last_exit: Option<&crate::state::ExitRecord>) -> bool {
+    match last_exit {
+        // 从未启动过，只有 always 策略在守护进程启动时拉起
+        None => ele.restart_policy() == RestartPolicy::Always,
+        Some(exit) if exit.stopped || exit.crash_loop => false,
+        // 不论退出码，健康检查失败都视为异常退出
+        Some(exit) if exit.unhealthy => ele.restart_policy() != RestartPolicy::Never,
+        Some(exit) => match ele.restart_policy() {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => exit.exit_code != Some(0),
+            RestartPolicy::Always => true,
+        },
+    }
+}
+
+/// 第 n 次重启前的等待时长，每次翻倍
+fn backoff(base: u64, retries: u32) -> Duration {
+    Duration::from_secs(base)
+        .saturating_mul(2u32.saturating_pow(retries))
+        .min(MAX_BACKOFF)
+}
+
+fn log(msg: &str) {
+    println!("[{}] {}", Local::now().format("%Y-%m-%d %H:%M:%S"), msg);
+}
+
+/// 后台线程结束时（包括 panic）通知主循环请求已处理完
+struct Done {
+    names: Vec<String>,
+    background_tx: Sender<Background>,
+}
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        let names = std::mem::take(&mut self.names);
+        let _ = self.background_tx.send(Background::Done(names));
+    }
+}
+
+/// 把后台线程启动的子进程交给主循环持有
+fn hand_over(
+    background_tx: &Sender<Background>,
+    pm: &ProcessManager,
+    name: &str,
+    status: &StartStatus,
+    child: Option<Child>,
+) {
+    let run_id = pm
+        .state
+        .get(name)
+        .map(|it| it.run_id.clone())
+        .unwrap_or_default();
+    // 主循环已退出时子进程不再被回收，不影响回复
+    let _ = background_tx.send(Background::Started(Started {
+        name: name.to_string(),
+        status: status.clone(),
+        child,
+        run_id,
+    }));
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, env};
+
+use crate::error::{Error, Result};
+
+/// 模板变量
+///
+/// - `{name}`、`{type}`：唯一程序名、进程类型
+/// - `{PM_PATH}`：配置目录的绝对路径
+/// - `{date}`：启动日期，`yyyy-MM-dd`
+/// - `{run_id}`：本次启动的唯一标识，预览时未运行的进程保留原样
+/// - `{instance}`：实例序号，目前每个进程只有一个实例，固定为 0
+/// - `${ENV}`：进程的环境变量，即 env、env_files 中的变量，没有开启 clear_env 时还包括 pm 的环境变量
+///
+/// `{{`、`}}` 输出字面量的 `{`、`}`，花括号中不是变量名（如 `{print $2}`、`{}`）时原样保留
+#[derive(Clone)]
+pub struct TemplateContext {
+    vars: HashMap<&'static str, String>,
+    /// 为 false 时保留 `{run_id}` 原样
+    has_run_id: bool,
+    /// 进程自己的环境变量，优先于 pm 的环境变量
+    env: HashMap<String, String>,
+    /// 是否使用 pm 的环境变量
+    inherit_env: bool,
+    /// 为 false 时（有环境变量文件无法读取）找不到的 `${ENV}` 原样保留，启动时会报告文件错误
+    env_complete: bool,
+}
+
+impl TemplateContext {
+    pub fn new(
+        name: &str,
+        process_type: &str,
+        pm_path: &str,
+        date: &str,
+        run_id: Option<&str>,
+    ) -> Self {
+        let mut vars = HashMap::new();
+        vars.insert("name", name.to_string());
+        vars.insert("type", process_type.to_string());
+        vars.insert("PM_PATH", pm_path.to_string());
+        vars.insert("date", date.to_string());
+        vars.insert("instance", "0".to_string());
+        if let Some(run_id) = run_id {
+            vars.insert("run_id", run_id.to_string());
+        }
+        TemplateContext {
+            vars,
+            has_run_id: run_id.is_some(),
+            env: HashMap::new(),
+            inherit_env: true,
+            env_complete: true,
+        }
+    }
+
+    /// 使用进程的环境变量展开 `${ENV}`，`inherit_env` 为 false 时不再使用 pm 的环境变量
+    pub fn with_env<I>(mut self, env: I, inherit_env: bool, env_complete: bool) -> Self
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        self.env.extend(env);
+        self.inherit_env = inherit_env;
+        self.env_complete = env_complete;
+        self
+    }
+
+    fn env_var(&self, var: &str) -> Option<String> {
+        self.env
+            .get(var)
+            .cloned()
+            .or_else(|| env::var(var).ok().filter(|_| self.inherit_env))
+    }
+
+    /// 展开模板，遇到未知变量或不存在的环境变量时返回错误
+    pub fn expand(&self, template: &str) -> Result<String> {
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(idx) = rest.find(['{', '}', '$']) {
+            out.push_str(&rest[..idx]);
+            rest = &rest[idx..];
+
+            if let Some(tail) = rest.strip_prefix("{{") {
+                out.push('{');
+                rest = tail;
+            } else if let Some(tail) = rest.strip_prefix("}}") {
+                out.push('}');
+                rest = tail;
+            } else if let Some((var, tail)) = rest.strip_prefix("${").and_then(split_ident) {
+                match self.env_var(var) {
+                    Some(value) => out.push_str(&value),
+                    None if !self.env_complete => out.push_str(&format!("${{{}}}", var)),
+                    None => return Err(Error::Invalid(format!("环境变量不存在：${{{}}}", var))),
+                }
+                rest = tail;
+            } else if let Some((var, tail)) = rest.strip_prefix('{').and_then(split_ident) {
+                match self.vars.get(var) {
+                    Some(value) => out.push_str(value),
+                    None if var == "run_id" && !self.has_run_id => out.push_str("{run_id}"),
+                    None => {
+                        return Err(Error::Invalid(format!(
+                            "未知变量：{{{}}}，字面量花括号请写成 {{{{ 和 }}}}",
+                            var
+                        )))
+                    }
+                }
+                rest = tail;
+            } else {
+                let ch = rest.chars().next().unwrap();
+                out.push(ch);
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+        out.push_str(rest);
+
+        Ok(out)
+    }
+}
+
+/// 拆分 `ident}rest`，花括号中不是合法变量名时返回 None
+fn split_ident(s: &str) -> Option<(&str, &str)> {
+    let end = s.find('}')?;
+    let ident = &s[..end];
+    let mut chars = ident.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|it| it.is_ascii_alphabetic() || it == '_')
+        && chars.all(|it| it.is_ascii_alphanumeric() || it == '_');
+    valid.then(|| (ident, &s[end + 1..]))
+}